@@ -0,0 +1,14 @@
+CREATE TABLE issue_deliveries (
+newsletter_issue_id uuid NOT NULL
+REFERENCES newsletter_issues (newsletter_issue_id),
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id),
+-- One of `sent`, `failed` or `skipped_invalid`
+status TEXT NOT NULL,
+provider_message_id TEXT,
+n_attempts SMALLINT NOT NULL,
+last_error TEXT,
+created_at timestamptz NOT NULL,
+updated_at timestamptz NOT NULL,
+PRIMARY KEY(newsletter_issue_id, subscriber_id)
+);
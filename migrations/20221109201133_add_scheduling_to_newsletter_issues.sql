@@ -0,0 +1,5 @@
+-- Scheduled issues are only published, and delivered, once `publish_at` is due.
+ALTER TABLE newsletter_issues
+ADD COLUMN status TEXT NOT NULL DEFAULT 'published',
+ADD COLUMN publish_at timestamptz NULL,
+ALTER COLUMN published_at DROP NOT NULL;
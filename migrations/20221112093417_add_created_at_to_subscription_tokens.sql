@@ -0,0 +1,10 @@
+-- Confirmation links expire, see `subscriptions.confirmation_token_ttl_hours`.
+BEGIN;
+ALTER TABLE subscription_tokens ADD COLUMN created_at timestamptz NULL;
+-- Existing tokens are as old as the subscription they were issued for
+UPDATE subscription_tokens t
+SET created_at = s.subscribed_at
+FROM subscriptions s
+WHERE s.id = t.subscriber_id;
+ALTER TABLE subscription_tokens ALTER COLUMN created_at SET NOT NULL;
+COMMIT;
@@ -0,0 +1,10 @@
+-- Only SHA3-256 hashes of confirmation tokens are stored, so that a leaked table
+-- cannot be used to confirm pending subscribers.
+BEGIN;
+CREATE EXTENSION IF NOT EXISTS pgcrypto;
+-- Pending links keep working: they are looked up by hash from now on
+UPDATE subscription_tokens
+SET subscription_token = encode(digest(subscription_token, 'sha3-256'), 'hex');
+ALTER TABLE subscription_tokens
+RENAME COLUMN subscription_token TO subscription_token_hash;
+COMMIT;
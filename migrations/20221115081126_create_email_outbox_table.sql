@@ -0,0 +1,15 @@
+-- Transactional emails are written here in the same transaction as the change
+-- that triggers them, then sent by the background worker.
+CREATE TABLE email_outbox (
+email_id uuid NOT NULL,
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id) ON DELETE CASCADE,
+recipient TEXT NOT NULL,
+subject TEXT NOT NULL,
+html_body TEXT NOT NULL,
+text_body TEXT NOT NULL,
+n_retries SMALLINT NOT NULL DEFAULT 0,
+execute_after timestamptz NOT NULL DEFAULT now(),
+created_at timestamptz NOT NULL,
+PRIMARY KEY(email_id)
+);
@@ -0,0 +1,10 @@
+-- Overrides for the templates shipped in `configuration/email_templates`,
+-- picked up without a restart.
+CREATE TABLE email_templates (
+name TEXT NOT NULL,
+subject TEXT NOT NULL,
+text_body TEXT NOT NULL,
+html_body TEXT NOT NULL,
+updated_at timestamptz NOT NULL DEFAULT now(),
+PRIMARY KEY(name)
+);
@@ -0,0 +1,5 @@
+-- Existing subscribers and template overrides were all written in English.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE email_templates ADD COLUMN locale TEXT NOT NULL DEFAULT 'en';
+ALTER TABLE email_templates DROP CONSTRAINT email_templates_pkey;
+ALTER TABLE email_templates ADD PRIMARY KEY (name, locale);
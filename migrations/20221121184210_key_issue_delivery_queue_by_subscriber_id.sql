@@ -0,0 +1,13 @@
+-- Deliveries are rendered for each recipient, which takes more than their email.
+ALTER TABLE issue_delivery_queue
+ADD COLUMN subscriber_id uuid REFERENCES subscriptions (id) ON DELETE CASCADE;
+UPDATE issue_delivery_queue q
+SET subscriber_id = s.id
+FROM subscriptions s
+WHERE s.email = q.subscriber_email;
+-- Their subscriber is gone, the worker would have skipped them anyway.
+DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+ALTER TABLE issue_delivery_queue ALTER COLUMN subscriber_id SET NOT NULL;
+ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_pkey;
+ALTER TABLE issue_delivery_queue DROP COLUMN subscriber_email;
+ALTER TABLE issue_delivery_queue ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
@@ -0,0 +1,3 @@
+-- Where the consent of subscribers who did not go through double opt-in comes from,
+-- e.g. the tool they were imported from.
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
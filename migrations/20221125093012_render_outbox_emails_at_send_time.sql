@@ -0,0 +1,14 @@
+-- Outbox emails are rendered when they are sent: queued confirmation emails used to
+-- hold the raw token of their link. Pending subscribers are sent a confirmation email,
+-- confirmed ones the notice that they are already on the list.
+ALTER TABLE email_outbox ADD COLUMN kind TEXT;
+UPDATE email_outbox o
+SET kind = CASE WHEN s.status = 'confirmed' THEN 'already_subscribed' ELSE 'confirmation' END
+FROM subscriptions s
+WHERE s.id = o.subscriber_id;
+ALTER TABLE email_outbox ALTER COLUMN kind SET NOT NULL;
+ALTER TABLE email_outbox
+DROP COLUMN recipient,
+DROP COLUMN subject,
+DROP COLUMN html_body,
+DROP COLUMN text_body;
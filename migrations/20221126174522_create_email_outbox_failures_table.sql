@@ -0,0 +1,11 @@
+-- Outbox emails we gave up on, kept for admins to follow up with their subscriber.
+CREATE TABLE email_outbox_failures (
+email_id uuid NOT NULL,
+subscriber_id uuid NOT NULL
+REFERENCES subscriptions (id) ON DELETE CASCADE,
+kind TEXT NOT NULL,
+n_retries SMALLINT NOT NULL,
+last_error TEXT NOT NULL,
+failed_at timestamptz NOT NULL,
+PRIMARY KEY(email_id)
+);
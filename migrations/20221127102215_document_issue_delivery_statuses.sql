@@ -0,0 +1,5 @@
+-- `sending` is claimed before handing a delivery over to the provider. A delivery
+-- still `sending` when it is picked up again was interrupted and becomes `unknown`:
+-- it may or may not have been delivered, an admin decides whether to requeue it.
+COMMENT ON COLUMN issue_deliveries.status IS
+'One of `sending`, `sent`, `retrying`, `failed`, `unknown` or `skipped_invalid`';
@@ -0,0 +1,102 @@
+-- Addresses that only differ in case are about to become the same subscriber, so
+-- existing case variants are merged first. The confirmed subscription is kept, or
+-- the oldest one if none (or several) of them are confirmed: its address, name,
+-- status and locale win.
+-- Tokens, outbox emails and their failures of the other variants are moved over to
+-- it. So are their queued deliveries, recorded deliveries and delivery failures,
+-- unless the kept subscription already has one for the same issue: the kept row's
+-- record wins, and the others are dropped along with the variants themselves.
+CREATE TEMPORARY TABLE merged_subscriptions AS
+SELECT id AS merged_id, kept_id
+FROM (
+    SELECT
+        id,
+        first_value(id) OVER (
+            PARTITION BY lower(email)
+            ORDER BY status = 'confirmed' DESC, subscribed_at, id
+        ) AS kept_id
+    FROM subscriptions
+) s
+WHERE id <> kept_id;
+
+UPDATE subscription_tokens t
+SET subscriber_id = m.kept_id
+FROM merged_subscriptions m
+WHERE t.subscriber_id = m.merged_id;
+
+UPDATE email_outbox o
+SET subscriber_id = m.kept_id
+FROM merged_subscriptions m
+WHERE o.subscriber_id = m.merged_id;
+
+UPDATE email_outbox_failures f
+SET subscriber_id = m.kept_id
+FROM merged_subscriptions m
+WHERE f.subscriber_id = m.merged_id;
+
+UPDATE issue_delivery_queue q
+SET subscriber_id = moved.kept_id
+FROM (
+    SELECT DISTINCT ON (q.newsletter_issue_id, m.kept_id)
+        q.newsletter_issue_id, q.subscriber_id, m.kept_id
+    FROM issue_delivery_queue q
+    JOIN merged_subscriptions m ON m.merged_id = q.subscriber_id
+    WHERE NOT EXISTS (
+        SELECT 1 FROM issue_delivery_queue k
+        WHERE k.newsletter_issue_id = q.newsletter_issue_id AND k.subscriber_id = m.kept_id
+    )
+    ORDER BY q.newsletter_issue_id, m.kept_id, q.subscriber_id
+) moved
+WHERE q.newsletter_issue_id = moved.newsletter_issue_id
+AND q.subscriber_id = moved.subscriber_id;
+
+-- A variant the issue was sent to wins over the others
+UPDATE issue_deliveries d
+SET subscriber_id = moved.kept_id
+FROM (
+    SELECT DISTINCT ON (d.newsletter_issue_id, m.kept_id)
+        d.newsletter_issue_id, d.subscriber_id, m.kept_id
+    FROM issue_deliveries d
+    JOIN merged_subscriptions m ON m.merged_id = d.subscriber_id
+    WHERE NOT EXISTS (
+        SELECT 1 FROM issue_deliveries k
+        WHERE k.newsletter_issue_id = d.newsletter_issue_id AND k.subscriber_id = m.kept_id
+    )
+    ORDER BY d.newsletter_issue_id, m.kept_id, d.status = 'sent' DESC, d.updated_at DESC
+) moved
+WHERE d.newsletter_issue_id = moved.newsletter_issue_id
+AND d.subscriber_id = moved.subscriber_id;
+
+-- Delivery failures are still keyed by address
+UPDATE issue_delivery_failures f
+SET subscriber_email = moved.kept_email
+FROM (
+    SELECT DISTINCT ON (f.newsletter_issue_id, k.id)
+        f.newsletter_issue_id, f.subscriber_email, k.email AS kept_email
+    FROM issue_delivery_failures f
+    JOIN subscriptions s ON s.email = f.subscriber_email
+    JOIN merged_subscriptions m ON m.merged_id = s.id
+    JOIN subscriptions k ON k.id = m.kept_id
+    WHERE NOT EXISTS (
+        SELECT 1 FROM issue_delivery_failures kf
+        WHERE kf.newsletter_issue_id = f.newsletter_issue_id AND kf.subscriber_email = k.email
+    )
+    ORDER BY f.newsletter_issue_id, k.id, f.failed_at DESC
+) moved
+WHERE f.newsletter_issue_id = moved.newsletter_issue_id
+AND f.subscriber_email = moved.subscriber_email;
+
+DELETE FROM issue_delivery_failures f
+USING subscriptions s, merged_subscriptions m
+WHERE s.email = f.subscriber_email AND s.id = m.merged_id;
+
+DELETE FROM issue_deliveries d
+USING merged_subscriptions m
+WHERE d.subscriber_id = m.merged_id;
+
+-- Their remaining queued deliveries go with them
+DELETE FROM subscriptions s
+USING merged_subscriptions m
+WHERE s.id = m.merged_id;
+
+DROP TABLE merged_subscriptions;
@@ -0,0 +1,4 @@
+-- Addresses that only differ in case belong to the same subscriber, both when they
+-- subscribe and when they are imported. Existing duplicates have to be merged by
+-- hand before this can be applied.
+CREATE UNIQUE INDEX subscriptions_lower_email_key ON subscriptions (lower(email));
@@ -0,0 +1,4 @@
+-- Subscribers who unsubscribe before their delivery goes out are recorded as
+-- `skipped_unsubscribed`.
+COMMENT ON COLUMN issue_deliveries.status IS
+'One of `sending`, `sent`, `retrying`, `failed`, `unknown`, `skipped_invalid` or `skipped_unsubscribed`';
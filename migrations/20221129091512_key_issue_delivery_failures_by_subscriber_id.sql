@@ -0,0 +1,14 @@
+-- Failed deliveries are requeued by subscriber, the address they were sent to may
+-- have changed case since.
+ALTER TABLE issue_delivery_failures
+ADD COLUMN subscriber_id uuid REFERENCES subscriptions (id) ON DELETE CASCADE;
+UPDATE issue_delivery_failures f
+SET subscriber_id = s.id
+FROM subscriptions s
+WHERE s.email = f.subscriber_email;
+-- Their subscriber is gone, there is nobody left to requeue them for.
+DELETE FROM issue_delivery_failures WHERE subscriber_id IS NULL;
+ALTER TABLE issue_delivery_failures ALTER COLUMN subscriber_id SET NOT NULL;
+ALTER TABLE issue_delivery_failures DROP CONSTRAINT issue_delivery_failures_pkey;
+ALTER TABLE issue_delivery_failures DROP COLUMN subscriber_email;
+ALTER TABLE issue_delivery_failures ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
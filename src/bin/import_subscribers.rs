@@ -0,0 +1,64 @@
+//! Imports subscribers from a CSV file, see `zero2prod::subscriber_import`.
+//!
+//! ```text
+//! import_subscribers <file.csv>
+//! import_subscribers --confirmed "<where they gave their consent>" <file.csv>
+//! ```
+//!
+//! Confirmation emails are queued: the application sends them once running.
+use anyhow::Context;
+use zero2prod::configuration::get_configuration;
+use zero2prod::startup::get_connection_pool;
+use zero2prod::subscriber_import::{import_subscribers, ImportMode, RowOutcome};
+use zero2prod::telemetry::{get_subscriber, init_subscriber};
+
+const USAGE: &str = "Usage: import_subscribers [--confirmed <consent source>] <file.csv>";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // The report goes to stdout, logs stay out of its way.
+    let subscriber = get_subscriber("import_subscribers".into(), "warn".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (mode, path) = match args.as_slice() {
+        [path] => (ImportMode::Pending, path),
+        [flag, consent_source, path]
+            if flag == "--confirmed" && !consent_source.trim().is_empty() =>
+        {
+            (
+                ImportMode::Confirmed {
+                    consent_source: consent_source.trim().to_owned(),
+                },
+                path,
+            )
+        }
+        _ => anyhow::bail!(USAGE),
+    };
+    let csv = std::fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+
+    let configuration = get_configuration().expect("Failed to read configuration.");
+    let pool = get_connection_pool(&configuration.database);
+    let report = import_subscribers(
+        &pool,
+        configuration.subscriptions.default_locale,
+        &mode,
+        &csv,
+    )
+    .await?;
+
+    for row in &report.rows {
+        match &row.outcome {
+            RowOutcome::Accepted => println!("{}\t{}\taccepted", row.line, row.email),
+            RowOutcome::Rejected(reason) => {
+                println!("{}\t{}\trejected: {}", row.line, row.email, reason)
+            }
+        }
+    }
+    println!(
+        "{} accepted, {} rejected.",
+        report.n_accepted(),
+        report.n_rejected()
+    );
+    Ok(())
+}
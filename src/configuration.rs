@@ -1,5 +1,8 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::{
+    EmailClient, FileTransport, PostmarkTransport, RetryPolicy, SmtpTlsMode, SmtpTransport,
+    MAX_BATCH_SIZE,
+};
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{
@@ -14,15 +17,62 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub worker: WorkerSettings,
+    pub subscriptions: SubscriptionSettings,
+    pub email_templates: EmailTemplateSettings,
     pub redis_uri: Secret<String>,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub retry: EmailRetrySettings,
+    pub smtp: SmtpSettings,
+    pub file: FileTransportSettings,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    Postmark,
+    Smtp,
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailRetrySettings {
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+}
+
+impl EmailRetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            base_delay: std::time::Duration::from_millis(self.base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(self.max_delay_milliseconds),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTlsMode,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct FileTransportSettings {
+    pub directory: String,
 }
 
 impl EmailClientSettings {
@@ -36,15 +86,126 @@ impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        let retry_policy = self.retry.policy();
+        match self.transport {
+            EmailTransportKind::Postmark => EmailClient::new(
+                sender_email,
+                PostmarkTransport::new(self.base_url, self.authorization_token, timeout),
+                retry_policy,
+            ),
+            EmailTransportKind::Smtp => EmailClient::new(
+                sender_email,
+                self.smtp
+                    .transport(timeout)
+                    .expect("Invalid SMTP settings."),
+                retry_policy,
+            ),
+            EmailTransportKind::File => EmailClient::new(
+                sender_email,
+                FileTransport::new(self.file.directory),
+                retry_policy,
+            ),
+        }
+    }
+}
+
+impl SmtpSettings {
+    pub fn transport(self, timeout: std::time::Duration) -> Result<SmtpTransport, anyhow::Error> {
+        let credentials = self.username.zip(self.password);
+        SmtpTransport::new(&self.host, self.port, self.tls, credentials, timeout)
     }
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct WorkerSettings {
+    /// How many delivery loops run side by side, sharing one pool and email client.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    /// How many queued deliveries a loop picks up, and sends, in one go.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: usize,
+    pub poll_interval_milliseconds: u64,
+    pub error_backoff_milliseconds: u64,
+    /// How many issues each loop keeps in memory.
+    pub issue_cache_size: usize,
+    /// How often we check for scheduled issues that are due.
+    pub scheduler_interval_milliseconds: u64,
+    /// Deliveries that still fail after this many attempts are moved to the
+    /// dead-letter table.
+    pub max_attempts: u32,
+    pub retry_base_delay_seconds: u64,
+    pub retry_max_delay_seconds: u64,
+}
+
+impl WorkerSettings {
+    pub fn concurrency(&self) -> usize {
+        self.concurrency.max(1)
+    }
+
+    /// Capped at the largest batch the email provider accepts.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size.clamp(1, MAX_BATCH_SIZE)
+    }
+
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    pub fn error_backoff(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.error_backoff_milliseconds)
+    }
+
+    pub fn scheduler_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.scheduler_interval_milliseconds)
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.max(1),
+            base_delay: std::time::Duration::from_secs(self.retry_base_delay_seconds),
+            max_delay: std::time::Duration::from_secs(self.retry_max_delay_seconds),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionSettings {
+    /// How long confirmation links stay valid.
+    pub confirmation_token_ttl_hours: u64,
+    /// How long expired confirmation tokens are kept, so that their links keep saying
+    /// they expired rather than that they are unknown.
+    pub expired_token_retention_hours: u64,
+    /// How often expired confirmation tokens are deleted.
+    pub cleanup_interval_seconds: u64,
+    /// Also delete the subscribers that let their last confirmation link expire.
+    pub purge_unconfirmed_subscribers: bool,
+    /// Where subscribers are sent once confirmed. We render a page of our own if unset.
+    pub confirmation_redirect_url: Option<String>,
+    /// Used when neither the subscriber nor their browser asks for a supported locale.
+    pub default_locale: Locale,
+}
+
+impl SubscriptionSettings {
+    pub fn confirmation_token_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.confirmation_token_ttl_hours * 60 * 60)
+    }
+
+    pub fn expired_token_retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.expired_token_retention_hours * 60 * 60)
+    }
+
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailTemplateSettings {
+    /// Holds one directory of default templates per locale, overridden by rows of the
+    /// `email_templates` table.
+    pub directory: String,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct ApplicationSettings {
     #[serde(deserialize_with = "deserialize_number_from_string")]
@@ -52,6 +213,13 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub shutdown_timeout_seconds: u64,
+}
+
+impl ApplicationSettings {
+    pub fn shutdown_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_timeout_seconds)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
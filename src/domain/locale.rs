@@ -0,0 +1,75 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    De,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 2] = [Locale::En, Locale::De];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+
+    /// Only looks at the language, `de-AT` is served as `de`.
+    pub fn parse(s: &str) -> Option<Locale> {
+        let language = s.trim().split(['-', '_']).next()?;
+        Locale::ALL
+            .into_iter()
+            .find(|locale| locale.as_str().eq_ignore_ascii_case(language))
+    }
+
+    /// The supported locale the client prefers the most, if any.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        let mut ranges: Vec<(Locale, f32)> = header
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let locale = Locale::parse(parts.next()?)?;
+                let quality = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (quality > 0.0).then_some((locale, quality))
+            })
+            .collect();
+        // Stable, so ties keep the order of the header.
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges.first().map(|(locale, _)| *locale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+    use claim::{assert_none, assert_some_eq};
+
+    #[test]
+    fn regions_are_ignored() {
+        assert_some_eq!(Locale::parse("de-AT"), Locale::De);
+        assert_some_eq!(Locale::parse("EN_gb"), Locale::En);
+    }
+
+    #[test]
+    fn unsupported_languages_are_rejected() {
+        assert_none!(Locale::parse("fr"));
+        assert_none!(Locale::parse(""));
+    }
+
+    #[test]
+    fn the_supported_locale_with_the_highest_quality_wins() {
+        assert_some_eq!(
+            Locale::from_accept_language("fr-FR, en;q=0.5, de;q=0.8"),
+            Locale::De
+        );
+        assert_some_eq!(Locale::from_accept_language("de, en"), Locale::De);
+    }
+
+    #[test]
+    fn excluded_and_unsupported_languages_are_skipped() {
+        assert_none!(Locale::from_accept_language("de;q=0, fr, *"));
+    }
+}
@@ -1,7 +1,9 @@
+mod locale;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
 
+pub use locale::Locale;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
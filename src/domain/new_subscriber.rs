@@ -1,8 +1,9 @@
-use crate::domain::SubscriberName;
+use crate::domain::{Locale, SubscriberName};
 
 use super::subscriber_email::SubscriberEmail;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub locale: Locale,
 }
@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+/// The error details reported by the email provider for a rejected message.
+#[derive(Debug)]
+pub struct ProviderError {
+    pub error_code: Option<i64>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.error_code {
+            Some(code) => write!(f, "{} (error code {})", self.message, code),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailError {
+    #[error("Failed to build a valid email message")]
+    InvalidMessage(#[source] anyhow::Error),
+    #[error("The email provider rejected the recipient address")]
+    InvalidRecipient(#[source] ProviderError),
+    #[error("The recipient is inactive or suppressed at the email provider")]
+    InactiveRecipient(#[source] ProviderError),
+    #[error("The email provider rejected our credentials")]
+    Unauthorized(#[source] ProviderError),
+    #[error("The email provider is rate limiting us")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("The email provider is temporarily unavailable")]
+    Unavailable {
+        retry_after: Option<Duration>,
+        #[source]
+        source: ProviderError,
+    },
+    #[error("The email provider rejected the email")]
+    Rejected(#[source] ProviderError),
+    #[error("Failed to hand the email over to the transport")]
+    Transport(#[source] anyhow::Error),
+}
+
+impl EmailError {
+    /// Whether sending the very same email again later might succeed.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            EmailError::RateLimited { .. }
+            | EmailError::Unavailable { .. }
+            | EmailError::Transport(_) => true,
+            EmailError::InvalidMessage(_)
+            | EmailError::InvalidRecipient(_)
+            | EmailError::InactiveRecipient(_)
+            | EmailError::Unauthorized(_)
+            | EmailError::Rejected(_) => false,
+        }
+    }
+
+    /// Whether the error is caused by the recipient address rather than by the
+    /// message or by our setup: sending anything else to it is pointless.
+    pub fn is_undeliverable_recipient(&self) -> bool {
+        matches!(
+            self,
+            EmailError::InvalidRecipient(_) | EmailError::InactiveRecipient(_)
+        )
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            EmailError::RateLimited { retry_after }
+            | EmailError::Unavailable { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
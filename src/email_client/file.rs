@@ -0,0 +1,84 @@
+use super::{Email, EmailError, EmailTransport, SentEmail};
+use chrono::Utc;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes every email as a JSON document into a local outbox directory instead of
+/// delivering it. Handy for local development and CI, where no provider is reachable.
+pub struct FileTransport {
+    directory: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailError> {
+        let content =
+            serde_json::to_vec_pretty(email).map_err(|e| EmailError::InvalidMessage(e.into()))?;
+        let message_id = format!(
+            "{}-{}",
+            Utc::now().format("%Y%m%dT%H%M%S%.3f"),
+            Uuid::new_v4()
+        );
+        let filename = format!("{}.json", message_id);
+
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .map_err(|e| EmailError::Transport(e.into()))?;
+        tokio::fs::write(self.directory.join(filename), content)
+            .await
+            .map_err(|e| EmailError::Transport(e.into()))?;
+
+        Ok(SentEmail {
+            message_id: Some(message_id),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileTransport;
+    use crate::email_client::{Email, EmailTransport};
+    use claim::assert_ok;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn send_writes_the_email_into_the_outbox_directory() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let transport = FileTransport::new(&directory);
+        let email = Email {
+            from: "sender@example.com",
+            to: "recipient@example.com",
+            subject: "Subject",
+            html_body: "<p>Hello</p>",
+            text_body: "Hello",
+            headers: &[("Message-ID", "<1@example.com>")],
+        };
+
+        let sent = assert_ok!(transport.send(&email).await);
+
+        let entries: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].file_stem().unwrap().to_str(),
+            sent.message_id.as_deref()
+        );
+        let saved: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&entries[0]).unwrap()).unwrap();
+        assert_eq!(saved["to"], "recipient@example.com");
+        assert_eq!(saved["subject"], "Subject");
+        assert_eq!(saved["text_body"], "Hello");
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,552 @@
+mod error;
+mod file;
+mod postmark;
+mod retry;
+mod smtp;
+
+pub use error::{EmailError, ProviderError};
+pub use file::FileTransport;
+pub use postmark::PostmarkTransport;
+pub use retry::RetryPolicy;
+pub use smtp::{SmtpTlsMode, SmtpTransport};
+
+use crate::domain::SubscriberEmail;
+use tracing::Span;
+
+/// Postmark accepts at most 500 messages per batch request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+#[derive(serde::Serialize, Debug, Clone, Copy)]
+pub struct Email<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    /// Extra headers as (name, value) pairs.
+    pub headers: &'a [(&'a str, &'a str)],
+}
+
+/// What a transport reports back for an email it accepted.
+#[derive(Debug, Default, Clone)]
+pub struct SentEmail {
+    /// The id the provider assigned to the message, if it reports one.
+    pub message_id: Option<String>,
+}
+
+/// An email addressed to one recipient, sent on behalf of the configured sender.
+pub struct OutgoingEmail<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+}
+
+/// A backend able to deliver emails, e.g. an HTTP API or an SMTP relay.
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailError>;
+
+    /// Sends up to `MAX_BATCH_SIZE` emails, returning one outcome per email in the
+    /// same order. An `Err` means the whole batch failed.
+    ///
+    /// Backends without a batch API send the emails one at a time.
+    async fn send_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, EmailError> {
+        let mut outcomes = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcomes.push(self.send(email).await);
+        }
+        Ok(outcomes)
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+    retry_policy: RetryPolicy,
+}
+
+impl EmailClient {
+    pub fn new(
+        sender: SubscriberEmail,
+        transport: impl EmailTransport + 'static,
+        retry_policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+            retry_policy,
+        }
+    }
+
+    /// A `Message-ID` header value that is unique to `id` and stable across attempts,
+    /// so that receiving mail systems can tell a resent message from a new one.
+    pub fn message_id(&self, id: &str) -> String {
+        let domain = self
+            .sender
+            .as_ref()
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+        format!("<{}@{}>", id, domain)
+    }
+
+    #[tracing::instrument(
+        name = "Send an email",
+        skip_all,
+        fields(email.attempts = tracing::field::Empty)
+    )]
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_body: &str,
+        text_body: &str,
+    ) -> Result<SentEmail, EmailError> {
+        let email = Email {
+            from: self.sender.as_ref(),
+            to: recipient.as_ref(),
+            subject,
+            html_body,
+            text_body,
+            headers: &[],
+        };
+
+        let mut attempt = 1;
+        loop {
+            Span::current().record("email.attempts", attempt);
+            let e = match self.transport.send(&email).await {
+                Ok(sent) => return Ok(sent),
+                Err(e) if e.is_transient() => e,
+                Err(e) => return Err(e),
+            };
+            let delay = match self.retry_policy.next_delay(attempt, e.retry_after()) {
+                Some(delay) => delay,
+                None => return Err(e),
+            };
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                retry.attempt = attempt,
+                retry.delay_ms = delay.as_millis() as u64,
+                "Failed to send an email, retrying."
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Sends up to `MAX_BATCH_SIZE` emails, with a single request if the transport
+    /// supports batches.
+    ///
+    /// Transient failures are retried according to the retry policy, for the whole
+    /// batch or for the individual emails that failed. The returned outcomes are in
+    /// the same order as `emails`.
+    #[tracing::instrument(
+        name = "Send a batch of emails",
+        skip_all,
+        fields(email.batch_size = emails.len(), email.attempts = tracing::field::Empty)
+    )]
+    pub async fn send_batch(
+        &self,
+        emails: &[OutgoingEmail<'_>],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, EmailError> {
+        if emails.len() > MAX_BATCH_SIZE {
+            return Err(EmailError::InvalidMessage(anyhow::anyhow!(
+                "A batch can contain at most {} emails, got {}",
+                MAX_BATCH_SIZE,
+                emails.len()
+            )));
+        }
+        let emails: Vec<_> = emails
+            .iter()
+            .map(|e| Email {
+                from: self.sender.as_ref(),
+                to: e.recipient.as_ref(),
+                subject: e.subject,
+                html_body: e.html_body,
+                text_body: e.text_body,
+                headers: e.headers,
+            })
+            .collect();
+
+        let mut outcomes: Vec<Option<Result<SentEmail, EmailError>>> =
+            emails.iter().map(|_| None).collect();
+        let mut pending: Vec<usize> = (0..emails.len()).collect();
+        let mut attempt = 1;
+        while !pending.is_empty() {
+            Span::current().record("email.attempts", attempt);
+            let batch: Vec<_> = pending.iter().map(|&i| emails[i]).collect();
+            let mut retry_after = None;
+            let mut failed = Vec::new();
+            let batch_error = match self.transport.send_batch(&batch).await {
+                Ok(batch_outcomes) => {
+                    for (&i, outcome) in pending.iter().zip(batch_outcomes) {
+                        if let Err(e) = &outcome {
+                            if e.is_transient() {
+                                retry_after = retry_after.max(e.retry_after());
+                                failed.push(i);
+                            }
+                        }
+                        outcomes[i] = Some(outcome);
+                    }
+                    None
+                }
+                Err(e) if e.is_transient() => {
+                    retry_after = e.retry_after();
+                    failed = pending.clone();
+                    Some(e)
+                }
+                Err(e) => return Err(e),
+            };
+            pending = failed;
+            if pending.is_empty() {
+                break;
+            }
+
+            let delay = match self.retry_policy.next_delay(attempt, retry_after) {
+                Some(delay) => delay,
+                None => match batch_error {
+                    Some(e) => return Err(e),
+                    None => break,
+                },
+            };
+            tracing::warn!(
+                retry.attempt = attempt,
+                retry.delay_ms = delay.as_millis() as u64,
+                retry.n_emails = pending.len(),
+                "Failed to send part of a batch of emails, retrying."
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+
+        Ok(outcomes
+            .into_iter()
+            .map(|o| o.expect("Every email in the batch has an outcome"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        EmailClient, EmailError, OutgoingEmail, PostmarkTransport, RetryPolicy,
+    };
+    use claim::{assert_err, assert_matches, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use std::time::Duration;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                dbg!("body: {}", &body);
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        email_client_with_retries(base_url, RetryPolicy::no_retry())
+    }
+
+    fn email_client_with_retries(base_url: String, retry_policy: RetryPolicy) -> EmailClient {
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        );
+        EmailClient::new(email(), transport, retry_policy)
+    }
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_the_expected_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            // Not a 200 anymore!
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_transient_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), retry_policy());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_after_max_attempts() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), retry_policy());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_validation_errors() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), retry_policy());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_honors_retry_after() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), retry_policy());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let start = std::time::Instant::now();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_batch_sends_a_single_request_to_the_batch_endpoint() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (first, second) = (email(), email());
+        let (subject, content) = (subject(), content());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                { "ErrorCode": 0, "Message": "OK", "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817" },
+                { "ErrorCode": 406, "Message": "Inactive recipient" },
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client
+            .send_batch(&[
+                OutgoingEmail {
+                    recipient: &first,
+                    subject: &subject,
+                    html_body: &content,
+                    text_body: &content,
+                    headers: &[],
+                },
+                OutgoingEmail {
+                    recipient: &second,
+                    subject: &subject,
+                    html_body: &content,
+                    text_body: &content,
+                    headers: &[],
+                },
+            ])
+            .await
+            .unwrap();
+
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body.as_array().unwrap().len(), 2);
+        assert_eq!(body[1]["To"], second.as_ref());
+        let sent = assert_ok!(&outcomes[0]);
+        assert_eq!(
+            sent.message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert_matches!(&outcomes[1], Err(EmailError::InactiveRecipient(_)));
+    }
+
+    #[tokio::test]
+    async fn send_batch_retries_the_whole_batch_on_transient_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client_with_retries(mock_server.uri(), retry_policy());
+        let recipient = email();
+        let (subject, content) = (subject(), content());
+
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(500))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!([{ "ErrorCode": 0, "Message": "OK" }])),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcomes = email_client
+            .send_batch(&[OutgoingEmail {
+                recipient: &recipient,
+                subject: &subject,
+                html_body: &content,
+                text_body: &content,
+                headers: &[],
+            }])
+            .await
+            .unwrap();
+
+        assert_ok!(&outcomes[0]);
+    }
+}
@@ -0,0 +1,309 @@
+use super::{Email, EmailError, EmailTransport, ProviderError, SentEmail};
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: String,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailError> {
+        let request_body = SendEmailRequest::from(email);
+        let response = self.post("email", &request_body).await?;
+        // The message has been accepted at this point: a body we can't make sense of
+        // only costs us the message id.
+        let message_id = response
+            .json::<PostmarkResponse>()
+            .await
+            .ok()
+            .and_then(|r| r.message_id);
+
+        Ok(SentEmail { message_id })
+    }
+
+    async fn send_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<SentEmail, EmailError>>, EmailError> {
+        let request_body: Vec<_> = emails.iter().map(SendEmailRequest::from).collect();
+        let response = self.post("email/batch", &request_body).await?;
+        let results: Vec<PostmarkResponse> = response
+            .json()
+            .await
+            .map_err(|e| EmailError::Transport(e.into()))?;
+        if results.len() != emails.len() {
+            return Err(EmailError::Transport(anyhow::anyhow!(
+                "Postmark returned {} results for a batch of {} emails",
+                results.len(),
+                emails.len()
+            )));
+        }
+
+        // A batch request succeeds as a whole, every message reports its own outcome.
+        let outcomes = results
+            .into_iter()
+            .map(|result| match result.error_code {
+                0 => Ok(SentEmail {
+                    message_id: result.message_id,
+                }),
+                _ => Err(classify_error(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    Some(result),
+                    None,
+                )),
+            })
+            .collect();
+        Ok(outcomes)
+    }
+}
+
+impl PostmarkTransport {
+    async fn post(
+        &self,
+        endpoint: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<reqwest::Response, EmailError> {
+        let url = format!("{}/{}", self.base_url, endpoint);
+        let response = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| EmailError::Transport(e.into()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let retry_after = parse_retry_after(response.headers());
+        let body = response.json::<PostmarkResponse>().await.ok();
+        Err(classify_error(status, body, retry_after))
+    }
+}
+
+/// Postmark's response payload, see https://postmarkapp.com/developer/api/overview#error-codes
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkResponse {
+    error_code: i64,
+    message: String,
+    /// Only set for accepted messages.
+    #[serde(rename = "MessageID", default)]
+    message_id: Option<String>,
+}
+
+const INVALID_API_TOKEN: i64 = 10;
+const INVALID_EMAIL_REQUEST: i64 = 300;
+const INACTIVE_RECIPIENT: i64 = 406;
+
+fn classify_error(
+    status: StatusCode,
+    body: Option<PostmarkResponse>,
+    retry_after: Option<Duration>,
+) -> EmailError {
+    let provider_error = match body {
+        Some(body) => ProviderError {
+            error_code: Some(body.error_code),
+            message: body.message,
+        },
+        None => ProviderError {
+            error_code: None,
+            message: format!("Postmark responded with {}", status),
+        },
+    };
+
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        return EmailError::RateLimited { retry_after };
+    }
+    if status.is_server_error() {
+        return EmailError::Unavailable {
+            retry_after,
+            source: provider_error,
+        };
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        return EmailError::Unauthorized(provider_error);
+    }
+    match provider_error.error_code {
+        Some(INVALID_API_TOKEN) => EmailError::Unauthorized(provider_error),
+        Some(INVALID_EMAIL_REQUEST) => EmailError::InvalidRecipient(provider_error),
+        Some(INACTIVE_RECIPIENT) => EmailError::InactiveRecipient(provider_error),
+        _ => EmailError::Rejected(provider_error),
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now())
+        .to_std()
+        .ok()
+        .or(Some(Duration::ZERO))
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl<'a> From<&Email<'a>> for SendEmailRequest<'a> {
+    fn from(email: &Email<'a>) -> Self {
+        Self {
+            from: email.from,
+            to: email.to,
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email
+                .headers
+                .iter()
+                .map(|&(name, value)| Header { name, value })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify_error, parse_retry_after, PostmarkResponse};
+    use crate::email_client::EmailError;
+    use chrono::Utc;
+    use claim::{assert_matches, assert_none, assert_some, assert_some_eq};
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use reqwest::StatusCode;
+    use std::time::Duration;
+
+    fn body(error_code: i64, message: &str) -> Option<PostmarkResponse> {
+        Some(PostmarkResponse {
+            error_code,
+            message: message.into(),
+            message_id: None,
+        })
+    }
+
+    #[test]
+    fn invalid_to_address_is_an_invalid_recipient() {
+        let e = classify_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body(300, "Error parsing 'To': Illegal email address 'foo'."),
+            None,
+        );
+        assert_matches!(e, EmailError::InvalidRecipient(_));
+    }
+
+    #[test]
+    fn inactive_recipients_are_reported_as_such() {
+        let e = classify_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body(
+                406,
+                "You tried to send to a recipient that has been marked as inactive.",
+            ),
+            None,
+        );
+        assert_matches!(e, EmailError::InactiveRecipient(_));
+    }
+
+    #[test]
+    fn bad_server_tokens_are_auth_failures() {
+        let e = classify_error(StatusCode::UNAUTHORIZED, None, None);
+        assert_matches!(e, EmailError::Unauthorized(_));
+        let e = classify_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body(10, "Bad or missing API token"),
+            None,
+        );
+        assert_matches!(e, EmailError::Unauthorized(_));
+    }
+
+    #[test]
+    fn rate_limits_and_server_errors_are_transient() {
+        let retry_after = Some(Duration::from_secs(3));
+        let e = classify_error(StatusCode::TOO_MANY_REQUESTS, None, retry_after);
+        assert_matches!(e, EmailError::RateLimited { .. });
+        assert_eq!(e.retry_after(), retry_after);
+        let e = classify_error(StatusCode::SERVICE_UNAVAILABLE, None, None);
+        assert!(e.is_transient());
+    }
+
+    #[test]
+    fn other_validation_errors_are_rejections() {
+        let e = classify_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            body(400, "Sender signature not defined for From address."),
+            None,
+        );
+        assert_matches!(e, EmailError::Rejected(_));
+        assert!(!e.is_transient());
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_in_seconds_is_parsed() {
+        assert_some_eq!(parse_retry_after(&headers("120")), Duration::from_secs(120));
+    }
+
+    #[test]
+    fn retry_after_as_http_date_is_parsed() {
+        let date = (Utc::now() + chrono::Duration::seconds(30))
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string();
+        let delay = assert_some!(parse_retry_after(&headers(&date)));
+        assert!(delay <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn invalid_retry_after_is_ignored() {
+        assert_none!(parse_retry_after(&headers("soon")));
+        assert_none!(parse_retry_after(&HeaderMap::new()));
+    }
+}
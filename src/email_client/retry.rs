@@ -0,0 +1,85 @@
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy that gives up after the first failed attempt.
+    pub fn no_retry() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    /// How long to wait before the next attempt, or `None` if we should give up.
+    ///
+    /// `attempt` is the number of attempts made so far. A `Retry-After` hint from the
+    /// provider takes precedence over the exponential schedule, but we never wait longer
+    /// than `max_delay`: if the provider asks for more, we surface the error instead.
+    pub fn next_delay(&self, attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        if let Some(retry_after) = retry_after {
+            return (retry_after <= self.max_delay).then_some(retry_after);
+        }
+
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt - 1))
+            .min(self.max_delay);
+        // "Equal jitter": keep half of the exponential delay, randomise the other half.
+        let half = exponential / 2;
+        let jitter = rand::thread_rng().gen_range(Duration::ZERO..=half);
+        Some(half + jitter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use claim::{assert_none, assert_some_eq};
+    use std::time::Duration;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+        }
+    }
+
+    #[test]
+    fn no_delay_is_returned_once_all_attempts_are_used() {
+        assert_none!(policy().next_delay(10, None));
+        assert_none!(RetryPolicy::no_retry().next_delay(1, None));
+    }
+
+    #[test]
+    fn delays_grow_exponentially_and_are_capped() {
+        let policy = policy();
+        for (attempt, expected) in [(1, 100), (2, 200), (3, 400), (4, 800), (5, 1000), (9, 1000)] {
+            let delay = policy.next_delay(attempt, None).unwrap();
+            let expected = Duration::from_millis(expected);
+            assert!(delay >= expected / 2 && delay <= expected, "{:?}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_after_takes_precedence_over_the_exponential_schedule() {
+        let retry_after = Duration::from_millis(750);
+        assert_some_eq!(policy().next_delay(1, Some(retry_after)), retry_after);
+    }
+
+    #[test]
+    fn retry_after_longer_than_the_max_delay_gives_up() {
+        assert_none!(policy().next_delay(1, Some(Duration::from_secs(60))));
+    }
+}
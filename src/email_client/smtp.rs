@@ -0,0 +1,127 @@
+use super::{Email, EmailError, EmailTransport, ProviderError, SentEmail};
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTlsMode {
+    /// Plain-text connection, only meant for local relays such as MailHog.
+    None,
+    StartTls,
+    Tls,
+}
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls_mode: SmtpTlsMode,
+        credentials: Option<(String, Secret<String>)>,
+        timeout: std::time::Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls_mode {
+            SmtpTlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTlsMode::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpTlsMode::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+
+        Ok(Self {
+            mailer: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, email: &Email<'_>) -> Result<SentEmail, EmailError> {
+        let message = build_message(email).map_err(EmailError::InvalidMessage)?;
+        // SMTP relays don't hand out ids, the Message-ID header is the best we have.
+        let message_id = message.headers().get_raw("Message-ID").map(str::to_owned);
+        self.mailer.send(message).await.map_err(classify_error)?;
+
+        Ok(SentEmail { message_id })
+    }
+}
+
+fn classify_error(e: lettre::transport::smtp::Error) -> EmailError {
+    if !e.is_permanent() {
+        return EmailError::Transport(e.into());
+    }
+    let error_code = e
+        .status()
+        .and_then(|code| code.to_string().parse::<i64>().ok());
+    let provider_error = ProviderError {
+        error_code,
+        message: e.to_string(),
+    };
+    match error_code {
+        // Authentication failed
+        Some(535) => EmailError::Unauthorized(provider_error),
+        // Mailbox unavailable, user not local, mailbox name not allowed
+        Some(550) | Some(551) | Some(553) => EmailError::InvalidRecipient(provider_error),
+        _ => EmailError::Rejected(provider_error),
+    }
+}
+
+fn build_message(email: &Email<'_>) -> Result<Message, anyhow::Error> {
+    let mut builder = Message::builder()
+        .from(email.from.parse::<Mailbox>()?)
+        .to(email.to.parse::<Mailbox>()?)
+        .subject(email.subject);
+    for &(name, value) in email.headers {
+        // lettre generates a Message-ID unless we provide one.
+        builder = if name.eq_ignore_ascii_case("Message-ID") {
+            builder.message_id(Some(value.to_owned()))
+        } else {
+            let name = HeaderName::new_from_ascii(name.to_owned())?;
+            builder.raw_header(HeaderValue::new(name, value.to_owned()))
+        };
+    }
+    let message = builder.multipart(MultiPart::alternative_plain_html(
+        email.text_body.to_owned(),
+        email.html_body.to_owned(),
+    ))?;
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_message;
+    use crate::email_client::Email;
+
+    #[test]
+    fn custom_headers_are_added_to_the_message() {
+        let email = Email {
+            from: "sender@example.com",
+            to: "recipient@example.com",
+            subject: "Subject",
+            html_body: "<p>Hello</p>",
+            text_body: "Hello",
+            headers: &[
+                ("Message-ID", "<1.2@example.com>"),
+                ("X-Newsletter-Issue", "1"),
+            ],
+        };
+
+        let message = build_message(&email).unwrap();
+
+        let headers = message.headers();
+        assert_eq!(headers.get_raw("Message-ID"), Some("<1.2@example.com>"));
+        assert_eq!(headers.get_raw("X-Newsletter-Issue"), Some("1"));
+    }
+}
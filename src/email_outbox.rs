@@ -0,0 +1,409 @@
+use crate::configuration::WorkerSettings;
+use crate::domain::{Locale, SubscriberEmail};
+use crate::email_client::{EmailClient, EmailError, RetryPolicy};
+use crate::email_templates::{EmailTemplates, RenderedEmail, TemplateKind};
+use crate::issue_delivery_worker::{error_chain, listen, wait_for_tasks, ExecutionOutcome};
+use crate::routes::{generate_subscription_token, store_token};
+use crate::shutdown::Shutdown;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use tracing::Span;
+use uuid::Uuid;
+
+/// The channel `enqueue_email` notifies whenever an email is written to the outbox.
+pub const OUTBOX_CHANNEL: &str = "email_outbox";
+
+/// Renders outbox emails when they are sent.
+#[derive(Clone)]
+pub struct OutboxRenderer {
+    email_templates: EmailTemplates,
+    base_url: String,
+    default_locale: Locale,
+}
+
+impl OutboxRenderer {
+    pub fn new(email_templates: EmailTemplates, base_url: String, default_locale: Locale) -> Self {
+        Self {
+            email_templates,
+            base_url,
+            default_locale,
+        }
+    }
+
+    /// Confirmation emails come with a new token. It must only be stored once the
+    /// provider has accepted the email, so that earlier links keep working until then.
+    async fn render(
+        &self,
+        transaction: &mut PgTransaction,
+        email: &StoredEmail,
+        kind: TemplateKind,
+    ) -> Result<(RenderedEmail, Option<String>), anyhow::Error> {
+        let locale = Locale::parse(&email.locale).unwrap_or(self.default_locale);
+        match kind {
+            TemplateKind::Confirmation => {
+                let subscription_token = generate_subscription_token();
+                let confirmation_link = format!(
+                    "{}/subscriptions/confirm?subscription_token={}",
+                    self.base_url, subscription_token,
+                );
+                let rendered = self
+                    .email_templates
+                    .render(
+                        &mut *transaction,
+                        kind,
+                        locale,
+                        &[
+                            ("name", &email.name),
+                            ("confirmation_link", &confirmation_link),
+                        ],
+                    )
+                    .await?;
+                Ok((rendered, Some(subscription_token)))
+            }
+            TemplateKind::AlreadySubscribed => {
+                let rendered = self
+                    .email_templates
+                    .render(&mut *transaction, kind, locale, &[("name", &email.name)])
+                    .await?;
+                Ok((rendered, None))
+            }
+        }
+    }
+}
+
+/// The email is rendered when it is sent, so that the outbox never holds a
+/// confirmation link. It only goes out once `transaction` commits.
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kind: TemplateKind,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO email_outbox (email_id, subscriber_id, kind, created_at)
+VALUES ($1, $2, $3, now())
+"#,
+        Uuid::new_v4(),
+        subscriber_id,
+        kind.name(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    notify_outbox(&mut *transaction).await?;
+
+    Ok(())
+}
+
+async fn notify_outbox<'c, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'c>,
+{
+    sqlx::query!("SELECT pg_notify($1, '')", OUTBOX_CHANNEL)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+struct StoredEmail {
+    email_id: Uuid,
+    subscriber_id: Uuid,
+    kind: String,
+    n_retries: i16,
+    email: String,
+    name: String,
+    status: String,
+    locale: String,
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+/// Sends up to a batch of emails from the outbox. Each email is sent in its own
+/// transaction, so that its row lock is only held while that email is sent.
+/// Emails are sent at least once: if we crash before committing, the email goes
+/// out again.
+#[tracing::instrument(skip_all, fields(n_emails = tracing::field::Empty), err)]
+pub async fn try_send_outbox_emails(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    renderer: &OutboxRenderer,
+    settings: &WorkerSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let retry_policy = settings.retry_policy();
+    let mut n_emails = 0;
+    while n_emails < settings.batch_size() {
+        match try_send_next_email(pool, email_client, renderer, &retry_policy).await? {
+            ExecutionOutcome::EmptyQueue => break,
+            ExecutionOutcome::TaskCompleted => n_emails += 1,
+        }
+    }
+    Span::current().record("n_emails", n_emails);
+    if n_emails == 0 {
+        Ok(ExecutionOutcome::EmptyQueue)
+    } else {
+        Ok(ExecutionOutcome::TaskCompleted)
+    }
+}
+
+async fn try_send_next_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    renderer: &OutboxRenderer,
+    retry_policy: &RetryPolicy,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let email = sqlx::query_as!(
+        StoredEmail,
+        r#"
+SELECT o.email_id, o.subscriber_id, o.kind, o.n_retries, s.email, s.name, s.status, s.locale
+FROM email_outbox o
+JOIN subscriptions s ON s.id = o.subscriber_id
+WHERE o.execute_after <= now()
+ORDER BY o.created_at
+FOR UPDATE OF o
+SKIP LOCKED
+LIMIT 1
+"#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    let email = match email {
+        Some(email) => email,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    send_email(
+        &mut transaction,
+        email_client,
+        renderer,
+        retry_policy,
+        &email,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+async fn send_email(
+    transaction: &mut PgTransaction,
+    email_client: &EmailClient,
+    renderer: &OutboxRenderer,
+    retry_policy: &RetryPolicy,
+    email: &StoredEmail,
+) -> Result<(), anyhow::Error> {
+    let kind = match TemplateKind::parse(&email.kind) {
+        Some(kind) => kind,
+        None => {
+            tracing::error!(
+                kind = %email.kind,
+                subscriber_id = %email.subscriber_id,
+                "Giving up on an email from the outbox. It has no template."
+            );
+            let last_error = format!("There is no `{}` email template.", email.kind);
+            dead_letter_email(&mut *transaction, email, &last_error).await?;
+            return Ok(());
+        }
+    };
+    // E.g. the subscriber confirmed through an earlier link, or unsubscribed.
+    if email.status != recipient_status(kind) {
+        tracing::info!(
+            kind = kind.name(),
+            subscriber_id = %email.subscriber_id,
+            "Dropping an email from the outbox. It no longer applies to the subscriber."
+        );
+        delete_email(&mut *transaction, email.email_id).await?;
+        return Ok(());
+    }
+    let recipient = match SubscriberEmail::parse(email.email.clone()) {
+        Ok(recipient) => recipient,
+        Err(e) => {
+            tracing::error!(
+                error.message = %e,
+                subscriber_id = %email.subscriber_id,
+                "Dropping an email from the outbox. The stored recipient is invalid."
+            );
+            update_subscriber_status(&mut *transaction, email.subscriber_id, "invalid").await?;
+            delete_email(&mut *transaction, email.email_id).await?;
+            return Ok(());
+        }
+    };
+    let (rendered, subscription_token) = renderer.render(&mut *transaction, email, kind).await?;
+    let outcome = email_client
+        .send_email(
+            &recipient,
+            &rendered.subject,
+            &rendered.html_body,
+            &rendered.text_body,
+        )
+        .await;
+    match outcome {
+        Ok(_) => {
+            if let Some(subscription_token) = subscription_token {
+                store_token(&mut *transaction, email.subscriber_id, &subscription_token)
+                    .await
+                    .context("Failed to store the confirmation token for a subscriber")?;
+            }
+            delete_email(&mut *transaction, email.email_id).await?;
+        }
+        Err(e @ EmailError::Unauthorized(_)) => {
+            // Leave the outbox untouched until the credentials are fixed.
+            return Err(anyhow::Error::new(e).context("Failed to send an outbox email."));
+        }
+        Err(e) if e.is_undeliverable_recipient() => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                subscriber_id = %email.subscriber_id,
+                "The email provider refused to deliver to a subscriber. \
+                Marking them as undeliverable."
+            );
+            let status = match e {
+                EmailError::InactiveRecipient(_) => "inactive",
+                _ => "invalid",
+            };
+            update_subscriber_status(&mut *transaction, email.subscriber_id, status).await?;
+            delete_email(&mut *transaction, email.email_id).await?;
+        }
+        Err(e) => {
+            let attempts = email.n_retries as u32 + 1;
+            let delay = if e.is_transient() {
+                retry_policy.next_delay(attempts, e.retry_after())
+            } else {
+                None
+            };
+            match delay {
+                Some(delay) => {
+                    tracing::warn!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        subscriber_id = %email.subscriber_id,
+                        attempts,
+                        "Failed to send an email from the outbox. Retrying in {:?}.",
+                        delay
+                    );
+                    reschedule_email(&mut *transaction, email.email_id, delay).await?;
+                }
+                None => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        subscriber_id = %email.subscriber_id,
+                        attempts,
+                        "Failed to send an email from the outbox. Giving up."
+                    );
+                    dead_letter_email(&mut *transaction, email, &error_chain(&e)).await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The status a subscriber must still have for an email of this kind to make sense.
+fn recipient_status(kind: TemplateKind) -> &'static str {
+    match kind {
+        TemplateKind::Confirmation => "pending_confirmation",
+        TemplateKind::AlreadySubscribed => "confirmed",
+    }
+}
+
+async fn delete_email(transaction: &mut PgTransaction, email_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM email_outbox WHERE email_id = $1"#, email_id)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Moves the email to `email_outbox_failures`, where admins can see it.
+async fn dead_letter_email(
+    transaction: &mut PgTransaction,
+    email: &StoredEmail,
+    last_error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO email_outbox_failures (
+email_id,
+subscriber_id,
+kind,
+n_retries,
+last_error,
+failed_at
+)
+VALUES ($1, $2, $3, $4, $5, now())
+"#,
+        email.email_id,
+        email.subscriber_id,
+        email.kind,
+        email.n_retries,
+        last_error,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_email(transaction, email.email_id).await
+}
+
+async fn reschedule_email(
+    transaction: &mut PgTransaction,
+    email_id: Uuid,
+    delay: std::time::Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+UPDATE email_outbox
+SET
+n_retries = n_retries + 1,
+execute_after = now() + make_interval(secs => $2)
+WHERE email_id = $1
+"#,
+        email_id,
+        delay.as_secs_f64()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+async fn update_subscriber_status(
+    transaction: &mut PgTransaction,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        status
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+pub async fn outbox_loop(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    renderer: OutboxRenderer,
+    settings: WorkerSettings,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let mut listener = listen(&pool, OUTBOX_CHANNEL).await;
+    while !shutdown.is_triggered() {
+        match try_send_outbox_emails(&pool, &email_client, &renderer, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::select! {
+                    _ = wait_for_tasks(listener.as_mut(), settings.poll_interval()) => {}
+                    _ = shutdown.triggered() => {}
+                }
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(_) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(settings.error_backoff()) => {}
+                    _ = shutdown.triggered() => {}
+                }
+            }
+        }
+    }
+    tracing::info!("Outbox worker has stopped.");
+    Ok(())
+}
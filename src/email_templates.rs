@@ -0,0 +1,258 @@
+use crate::domain::Locale;
+use crate::template::{Template, TemplateError};
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::{PgExecutor, PgPool};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TemplateKind {
+    Confirmation,
+    AlreadySubscribed,
+}
+
+impl TemplateKind {
+    pub const ALL: [TemplateKind; 2] =
+        [TemplateKind::Confirmation, TemplateKind::AlreadySubscribed];
+
+    /// Used for file names and as the key of database overrides.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TemplateKind::Confirmation => "confirmation",
+            TemplateKind::AlreadySubscribed => "already_subscribed",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    fn variables(&self) -> &'static [&'static str] {
+        match self {
+            TemplateKind::Confirmation => &["name", "confirmation_link"],
+            TemplateKind::AlreadySubscribed => &["name"],
+        }
+    }
+
+    /// Variables both bodies must use, or the email is pointless.
+    fn required_variables(&self) -> &'static [&'static str] {
+        match self {
+            TemplateKind::Confirmation => &["confirmation_link"],
+            TemplateKind::AlreadySubscribed => &[],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct EmailTemplate {
+    subject: Template,
+    text_body: Template,
+    html_body: Template,
+}
+
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+}
+
+impl EmailTemplate {
+    pub fn parse(
+        kind: TemplateKind,
+        subject: &str,
+        text_body: &str,
+        html_body: &str,
+    ) -> Result<Self, TemplateError> {
+        let template = Self {
+            subject: Template::parse(subject.trim(), kind.variables())?,
+            text_body: Template::parse(text_body, kind.variables())?,
+            html_body: Template::parse(html_body, kind.variables())?,
+        };
+        for variable in kind.required_variables() {
+            if !template.text_body.uses(variable) || !template.html_body.uses(variable) {
+                return Err(TemplateError::MissingVariable(variable.to_string()));
+            }
+        }
+        Ok(template)
+    }
+
+    /// Values are HTML-escaped in the HTML body.
+    pub fn render(&self, variables: &[(&str, &str)]) -> RenderedEmail {
+        RenderedEmail {
+            subject: self.subject.render(variables, str::to_string),
+            text_body: self.text_body.render(variables, str::to_string),
+            html_body: self.html_body.render(variables, encode_minimal),
+        }
+    }
+}
+
+/// The templates shipped in the configuration directory, which can be overridden
+/// at runtime through the `email_templates` table.
+#[derive(Clone)]
+pub struct EmailTemplates {
+    defaults: Arc<HashMap<(TemplateKind, Locale), EmailTemplate>>,
+}
+
+impl EmailTemplates {
+    /// Expects `<locale>/<name>.subject`, `.txt` and `.html` for every locale and kind.
+    pub fn load(directory: &Path) -> Result<Self, anyhow::Error> {
+        let mut defaults = HashMap::new();
+        for (kind, locale) in all_templates() {
+            let read = |extension: &str| {
+                let path =
+                    directory
+                        .join(locale.as_str())
+                        .join(format!("{}.{}", kind.name(), extension));
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            };
+            let template =
+                EmailTemplate::parse(kind, &read("subject")?, &read("txt")?, &read("html")?)
+                    .with_context(|| {
+                        format!(
+                            "Invalid `{}` email template for `{}`",
+                            kind.name(),
+                            locale.as_str()
+                        )
+                    })?;
+            defaults.insert((kind, locale), template);
+        }
+        Ok(Self {
+            defaults: Arc::new(defaults),
+        })
+    }
+
+    /// Fails if any of the overrides stored in the database is invalid.
+    pub async fn validate_overrides(pool: &PgPool) -> Result<(), anyhow::Error> {
+        for (kind, locale) in all_templates() {
+            get_override(pool, kind, locale)
+                .await?
+                .transpose()
+                .with_context(|| {
+                    format!(
+                        "Invalid `{}` email template override for `{}`",
+                        kind.name(),
+                        locale.as_str()
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Prefers the database override. An override that has been broken since startup
+    /// is logged and ignored.
+    pub async fn render<'c, E>(
+        &self,
+        executor: E,
+        kind: TemplateKind,
+        locale: Locale,
+        variables: &[(&str, &str)],
+    ) -> Result<RenderedEmail, anyhow::Error>
+    where
+        E: PgExecutor<'c>,
+    {
+        match get_override(executor, kind, locale).await? {
+            Some(Ok(template)) => return Ok(template.render(variables)),
+            Some(Err(e)) => {
+                tracing::error!(
+                    error.message = %e,
+                    template = kind.name(),
+                    locale = locale.as_str(),
+                    "Ignoring an invalid email template override."
+                );
+            }
+            None => {}
+        }
+        Ok(self.defaults[&(kind, locale)].render(variables))
+    }
+}
+
+#[tracing::instrument(skip(executor))]
+async fn get_override<'c, E>(
+    executor: E,
+    kind: TemplateKind,
+    locale: Locale,
+) -> Result<Option<Result<EmailTemplate, TemplateError>>, sqlx::Error>
+where
+    E: PgExecutor<'c>,
+{
+    let row = sqlx::query!(
+        r#"
+SELECT subject, text_body, html_body
+FROM email_templates
+WHERE name = $1 AND locale = $2
+"#,
+        kind.name(),
+        locale.as_str()
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|r| EmailTemplate::parse(kind, &r.subject, &r.text_body, &r.html_body)))
+}
+
+fn all_templates() -> impl Iterator<Item = (TemplateKind, Locale)> {
+    TemplateKind::ALL
+        .into_iter()
+        .flat_map(|kind| Locale::ALL.into_iter().map(move |locale| (kind, locale)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmailTemplate, TemplateError, TemplateKind};
+    use claim::{assert_err, assert_ok};
+
+    fn confirmation(text_body: &str) -> Result<EmailTemplate, TemplateError> {
+        EmailTemplate::parse(
+            TemplateKind::Confirmation,
+            "Welcome {{name}}!",
+            text_body,
+            r#"<a href="{{confirmation_link}}">Confirm</a>"#,
+        )
+    }
+
+    #[test]
+    fn variables_are_replaced() {
+        let template = assert_ok!(confirmation("Visit {{ confirmation_link }}, {{name}}."));
+        let rendered = template.render(&[("name", "Ursula"), ("confirmation_link", "link")]);
+
+        assert_eq!(rendered.subject, "Welcome Ursula!");
+        assert_eq!(rendered.text_body, "Visit link, Ursula.");
+        assert_eq!(rendered.html_body, r#"<a href="link">Confirm</a>"#);
+    }
+
+    #[test]
+    fn values_are_escaped_in_the_html_body_only() {
+        let template = assert_ok!(confirmation("{{name}} {{confirmation_link}}"));
+        let rendered = template.render(&[("name", "<b>"), ("confirmation_link", "\"")]);
+
+        assert_eq!(rendered.text_body, "<b> \"");
+        assert_eq!(rendered.html_body, r#"<a href="&quot;">Confirm</a>"#);
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_eq!(
+            assert_err!(confirmation("{{confirmation_link}} {{unsubscribe_link}}")),
+            TemplateError::UnknownVariable("unsubscribe_link".into())
+        );
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_eq!(
+            assert_err!(confirmation("{{confirmation_link}} {{name")),
+            TemplateError::Unclosed
+        );
+    }
+
+    #[test]
+    fn required_variables_must_be_used() {
+        assert_eq!(
+            assert_err!(confirmation("Welcome!")),
+            TemplateError::MissingVariable("confirmation_link".into())
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::domain::Locale;
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::HttpRequest;
+
+/// The strings of the pages subscribers see, outside of emails.
+pub struct Messages {
+    pub confirmation_title: &'static str,
+    pub confirmation_prompt: &'static str,
+    pub confirmation_button: &'static str,
+    pub confirmed: &'static str,
+    pub unknown_confirmation_token: &'static str,
+    pub expired_confirmation_token: &'static str,
+    pub unsubscribe_title: &'static str,
+    pub unsubscribe_prompt: &'static str,
+    pub unsubscribe_button: &'static str,
+    pub unsubscribed: &'static str,
+    pub invalid_unsubscribe_link: &'static str,
+    pub unexpected_error: &'static str,
+}
+
+const EN: Messages = Messages {
+    confirmation_title: "Subscription confirmation",
+    confirmation_prompt: "Please confirm your subscription to our newsletter.",
+    confirmation_button: "Confirm my subscription",
+    confirmed: "Thank you, your subscription is confirmed!",
+    unknown_confirmation_token: "There is no subscriber associated with the provided token.",
+    expired_confirmation_token:
+        "The confirmation link has expired. Subscribe again to receive a new confirmation email.",
+    unsubscribe_title: "Unsubscribe",
+    unsubscribe_prompt: "Do you want to stop receiving our newsletter?",
+    unsubscribe_button: "Unsubscribe",
+    unsubscribed: "You have been unsubscribed.",
+    invalid_unsubscribe_link: "The unsubscribe link is not valid.",
+    unexpected_error: "Something went wrong, please try again later.",
+};
+
+const DE: Messages = Messages {
+    confirmation_title: "Bestätigung des Abonnements",
+    confirmation_prompt: "Bitte bestätige dein Abonnement unseres Newsletters.",
+    confirmation_button: "Abonnement bestätigen",
+    confirmed: "Danke, dein Abonnement ist bestätigt!",
+    unknown_confirmation_token: "Zu diesem Link gibt es kein Abonnement.",
+    expired_confirmation_token: "Der Bestätigungslink ist abgelaufen. Melde dich erneut an, \
+        um eine neue Bestätigungs-E-Mail zu erhalten.",
+    unsubscribe_title: "Abmelden",
+    unsubscribe_prompt: "Möchtest du unseren Newsletter nicht mehr erhalten?",
+    unsubscribe_button: "Abmelden",
+    unsubscribed: "Du wurdest abgemeldet.",
+    invalid_unsubscribe_link: "Der Abmeldelink ist ungültig.",
+    unexpected_error: "Etwas ist schiefgelaufen, bitte versuche es später noch einmal.",
+};
+
+pub fn messages(locale: Locale) -> &'static Messages {
+    match locale {
+        Locale::En => &EN,
+        Locale::De => &DE,
+    }
+}
+
+/// The locale asked for through `Accept-Language`, if supported.
+pub fn requested_locale(request: &HttpRequest) -> Option<Locale> {
+    request
+        .headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(Locale::from_accept_language)
+}
@@ -1,9 +1,22 @@
+use crate::configuration::{Settings, WorkerSettings};
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::{configuration::Settings, startup::get_connection_pool};
+use crate::email_client::{EmailClient, EmailError, OutgoingEmail, RetryPolicy};
+use crate::email_outbox::{outbox_loop, OutboxRenderer};
+use crate::email_templates::EmailTemplates;
+use crate::newsletter_scheduler::scheduler_loop;
+use crate::newsletter_template::{NewsletterTemplate, Recipient};
+use crate::shutdown::Shutdown;
+use crate::startup::get_connection_pool;
+use crate::subscription_cleanup::cleanup_loop;
+use crate::unsubscribe::UnsubscribeLinks;
+use sqlx::postgres::PgListener;
 use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
-use tracing::{field::display, Span};
+use tracing::Span;
 use uuid::Uuid;
 
 pub enum ExecutionOutcome {
@@ -11,116 +24,651 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    subscriber_name: String,
+    /// The subscriber has unsubscribed since the issue was published.
+    has_left: bool,
+    n_retries: i16,
+}
+
+impl Task {
+    fn key(&self) -> (Uuid, Uuid) {
+        (self.newsletter_issue_id, self.subscriber_id)
+    }
+}
+
 #[tracing::instrument(
-    skip_all, fields(
-        newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty),
+    skip_all,
+    fields(n_tasks = tracing::field::Empty, n_issue_queries = tracing::field::Empty),
     err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    settings: &WorkerSettings,
+    unsubscribe_links: &UnsubscribeLinks,
+    issue_cache: &mut IssueCache,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-    if task.is_none() {
+    let (mut transaction, tasks) = dequeue_tasks(pool, settings.batch_size() as i64).await?;
+    if tasks.is_empty() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
+    Span::current().record("n_tasks", tasks.len());
+    let retry_policy = settings.retry_policy();
 
-    let (transaction, issue_id, email) = task.unwrap();
-    Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                tracing::error!(error.cause_chain = ?e,
-                                error.message = %e,
-                                "Failed to deliver issue to a confirmed subscriber. Skipping.");
+    let issues = get_issues(pool, issue_cache, &tasks).await?;
+    // Tasks that are done with, successfully or not, and can leave the queue.
+    let mut completed = Vec::with_capacity(tasks.len());
+    let mut recipients = Vec::with_capacity(tasks.len());
+    for task in &tasks {
+        if task.has_left {
+            tracing::info!(
+                subscriber_email = %task.subscriber_email,
+                "Skipping a delivery, the subscriber has left the list."
+            );
+            record_delivery(&mut *transaction, task, "skipped_unsubscribed", None, None).await?;
+            completed.push(task);
+            continue;
+        }
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => recipients.push((task, email)),
+            Err(e) => {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid."
+                );
+                update_subscriber_status(&mut transaction, task.subscriber_id, "invalid").await?;
+                record_delivery(&mut *transaction, task, "skipped_invalid", None, None).await?;
+                completed.push(task);
             }
         }
+    }
+
+    // Claiming commits right away, so that we know what we were up to if we crash
+    // after handing the emails over to the provider.
+    let claims = claim_deliveries(pool, &recipients).await?;
+    let (recipients, unclaimed): (Vec<_>, Vec<_>) = recipients
+        .into_iter()
+        .partition(|(task, _)| claims[&task.key()] == Claim::Claimed);
+    for (task, _) in unclaimed {
+        if claims[&task.key()] == Claim::AlreadySent {
+            tracing::info!(
+                subscriber_email = %task.subscriber_email,
+                "Skipping a delivery, the issue has already been sent to this subscriber."
+            );
+            completed.push(task);
+            continue;
+        }
+        // The provider does not de-duplicate: sending it again might deliver the
+        // issue twice. An admin decides whether to requeue it.
+        tracing::warn!(
+            subscriber_email = %task.subscriber_email,
+            "Skipping a delivery that was interrupted while being sent. \
+            It may or may not have reached the subscriber."
+        );
+        let last_error = "Interrupted while being sent, it may have been delivered already.";
+        record_delivery(&mut *transaction, task, "unknown", None, Some(last_error)).await?;
+        dead_letter_task(&mut transaction, task, last_error).await?;
+    }
+
+    // Unsubscribe links follow RFC 8058, so that mail clients can offer one-click
+    // unsubscription.
+    let header_values: Vec<_> = recipients
+        .iter()
+        .map(|(task, _)| {
+            let (issue_id, subscriber_id) = task.key();
+            let unsubscribe_url = unsubscribe_links.url(subscriber_id);
+            (
+                email_client.message_id(&format!("{}.{}", issue_id, subscriber_id)),
+                format!("<{}>", unsubscribe_url),
+                unsubscribe_url,
+            )
+        })
+        .collect();
+    let contents: Vec<_> = recipients
+        .iter()
+        .zip(&header_values)
+        .map(|((task, email), (_, _, unsubscribe_url))| {
+            issues[&task.newsletter_issue_id].render(&Recipient {
+                name: &task.subscriber_name,
+                email: email.as_ref(),
+                unsubscribe_url,
+            })
+        })
+        .collect();
+    let headers: Vec<_> = header_values
+        .iter()
+        .map(|(message_id, list_unsubscribe, _)| {
+            [
+                ("Message-ID", message_id.as_str()),
+                ("List-Unsubscribe", list_unsubscribe.as_str()),
+                ("List-Unsubscribe-Post", "List-Unsubscribe=One-Click"),
+            ]
+        })
+        .collect();
+    let emails: Vec<_> = recipients
+        .iter()
+        .zip(&contents)
+        .zip(&headers)
+        .map(|(((_, email), content), headers)| OutgoingEmail {
+            recipient: email,
+            subject: &content.subject,
+            html_body: &content.html_body,
+            text_body: &content.text_body,
+            headers,
+        })
+        .collect();
+    match email_client.send_batch(&emails).await {
+        Ok(outcomes) => {
+            for ((task, _), outcome) in recipients.iter().zip(outcomes) {
+                match outcome {
+                    Ok(sent) => {
+                        // Recorded outside of the transaction: even if we fail to dequeue
+                        // the task, we must not send it again.
+                        let message_id = sent.message_id.as_deref();
+                        record_delivery(pool, task, "sent", message_id, None).await?;
+                        completed.push(task);
+                    }
+                    Err(e) if e.is_undeliverable_recipient() => {
+                        mark_undeliverable(&mut transaction, task, e).await?;
+                        completed.push(task);
+                    }
+                    Err(e) => {
+                        retry_or_dead_letter(&mut transaction, task, &e, &retry_policy).await?
+                    }
+                }
+            }
+        }
+        Err(e @ EmailError::Unauthorized(_)) => {
+            // Not the subscribers' fault and no amount of retrying will fix it:
+            // we leave the tasks in the queue, untouched, until the credentials are fixed.
+            let tasks: Vec<_> = recipients.iter().map(|(task, _)| *task).collect();
+            release_claims(pool, &tasks, &error_chain(&e)).await?;
+            return Err(anyhow::Error::new(e).context("Failed to deliver a batch of issues."));
+        }
         Err(e) => {
             tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Skipping a confirmed subscriber. Their stored contact details are invalid."
+                "Failed to deliver a batch of issues to confirmed subscribers."
             );
+            for (task, _) in &recipients {
+                retry_or_dead_letter(&mut transaction, task, &e, &retry_policy).await?;
+            }
         }
     }
-    delete_task(transaction, issue_id, &email).await?;
+    delete_tasks(&mut transaction, &completed).await?;
+    transaction.commit().await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+async fn mark_undeliverable(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    e: EmailError,
+) -> Result<(), anyhow::Error> {
+    tracing::warn!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        subscriber_email = %task.subscriber_email,
+        "The email provider refused to deliver to a confirmed subscriber. \
+        Marking them as undeliverable."
+    );
+    let status = match e {
+        EmailError::InactiveRecipient(_) => "inactive",
+        _ => "invalid",
+    };
+    update_subscriber_status(transaction, task.subscriber_id, status).await?;
+    record_delivery(
+        &mut *transaction,
+        task,
+        "failed",
+        None,
+        Some(&error_chain(&e)),
+    )
+    .await
+}
+
+/// Transient failures are rescheduled with exponential backoff until the retry
+/// budget is exhausted. Everything else ends up in the dead-letter table.
+async fn retry_or_dead_letter(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    e: &EmailError,
+    retry_policy: &RetryPolicy,
+) -> Result<(), anyhow::Error> {
+    let attempts = task.n_retries as u32 + 1;
+    let delay = if e.is_transient() {
+        retry_policy.next_delay(attempts, e.retry_after())
+    } else {
+        None
+    };
+    match delay {
+        Some(delay) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                subscriber_email = %task.subscriber_email,
+                attempts,
+                "Failed to deliver issue to a confirmed subscriber. Retrying in {:?}.",
+                delay
+            );
+            release_claims(&mut *transaction, &[task], &error_chain(e)).await?;
+            reschedule_task(transaction, task, delay).await
+        }
+        None => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                subscriber_email = %task.subscriber_email,
+                attempts,
+                "Failed to deliver issue to a confirmed subscriber. Giving up."
+            );
+            let last_error = error_chain(e);
+            record_delivery(&mut *transaction, task, "failed", None, Some(&last_error)).await?;
+            dead_letter_task(transaction, task, &last_error).await
+        }
+    }
+}
+
+pub(crate) fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut chain = e.to_string();
+    let mut current = e.source();
+    while let Some(cause) = current {
+        write!(chain, ": {}", cause).unwrap();
+        current = cause.source();
+    }
+    chain
+}
+
 type PgTransaction = Transaction<'static, Postgres>;
 
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: i64,
+) -> Result<(PgTransaction, Vec<Task>), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-SELECT newsletter_issue_id, subscriber_email
-FROM issue_delivery_queue
-FOR UPDATE
+SELECT
+q.newsletter_issue_id,
+q.subscriber_id,
+s.email AS subscriber_email,
+s.name AS subscriber_name,
+s.status = 'unsubscribed' AS "has_left!",
+q.n_retries
+FROM issue_delivery_queue q
+JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+JOIN subscriptions s ON s.id = q.subscriber_id
+-- Paused issues keep their place in the queue until they are resumed
+WHERE q.execute_after <= now() AND i.status = 'published'
+FOR UPDATE OF q
 SKIP LOCKED
-LIMIT 1
-"#
+LIMIT $1
+"#,
+        batch_size
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
 
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok((transaction, tasks))
 }
 
 #[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
-    issue_id: Uuid,
-    email: &str,
+async fn delete_tasks(
+    transaction: &mut PgTransaction,
+    tasks: &[&Task],
 ) -> Result<(), anyhow::Error> {
+    let (issue_ids, subscriber_ids): (Vec<_>, Vec<_>) = tasks.iter().map(|t| t.key()).unzip();
     sqlx::query!(
         r#"
 DELETE FROM issue_delivery_queue
 WHERE
-newsletter_issue_id = $1 AND subscriber_email = $2
+(newsletter_issue_id, subscriber_id) IN (
+SELECT * FROM UNNEST($1::uuid[], $2::uuid[])
+)
 "#,
-        issue_id,
-        email,
+        &issue_ids,
+        &subscriber_ids,
     )
-    .execute(&mut transaction)
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Claim {
+    Claimed,
+    AlreadySent,
+    /// A previous attempt was interrupted between handing the email over to the
+    /// provider and recording the outcome.
+    InDoubt,
+}
+
+/// Marks deliveries as `sending` before we hand them over to the provider, unless they
+/// have been sent already or might have been.
+#[tracing::instrument(skip_all)]
+async fn claim_deliveries(
+    pool: &PgPool,
+    recipients: &[(&Task, SubscriberEmail)],
+) -> Result<HashMap<(Uuid, Uuid), Claim>, anyhow::Error> {
+    let (issue_ids, subscriber_ids): (Vec<_>, Vec<_>) =
+        recipients.iter().map(|(task, _)| task.key()).unzip();
+    // The outer query sees `issue_deliveries` as it was before the insert, i.e. why
+    // the deliveries that we did not claim were left alone.
+    let claims = sqlx::query!(
+        r#"
+WITH claimed AS (
+INSERT INTO issue_deliveries (
+newsletter_issue_id,
+subscriber_id,
+status,
+n_attempts,
+created_at,
+updated_at
+)
+SELECT issue_id, subscriber_id, 'sending', 0, now(), now()
+FROM UNNEST($1::uuid[], $2::uuid[]) AS t(issue_id, subscriber_id)
+ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+SET
+status = 'sending',
+updated_at = now()
+WHERE issue_deliveries.status NOT IN ('sent', 'sending')
+RETURNING newsletter_issue_id, subscriber_id
+)
+SELECT
+t.issue_id AS "newsletter_issue_id!",
+t.subscriber_id AS "subscriber_id!",
+c.newsletter_issue_id IS NOT NULL AS "claimed!",
+d.status AS "previous_status?"
+FROM UNNEST($1::uuid[], $2::uuid[]) AS t(issue_id, subscriber_id)
+LEFT JOIN claimed c
+ON c.newsletter_issue_id = t.issue_id AND c.subscriber_id = t.subscriber_id
+LEFT JOIN issue_deliveries d
+ON d.newsletter_issue_id = t.issue_id AND d.subscriber_id = t.subscriber_id
+"#,
+        &issue_ids,
+        &subscriber_ids,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(claims
+        .into_iter()
+        .map(|r| {
+            let claim = if r.claimed {
+                Claim::Claimed
+            } else if r.previous_status.as_deref() == Some("sent") {
+                Claim::AlreadySent
+            } else {
+                Claim::InDoubt
+            };
+            ((r.newsletter_issue_id, r.subscriber_id), claim)
+        })
+        .collect())
+}
+
+/// Gives up our claim on deliveries that we know did not go out, so that they can
+/// be sent again.
+#[tracing::instrument(skip(executor, tasks))]
+async fn release_claims<'c, E>(
+    executor: E,
+    tasks: &[&Task],
+    last_error: &str,
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    let (issue_ids, subscriber_ids): (Vec<_>, Vec<_>) = tasks.iter().map(|t| t.key()).unzip();
+    sqlx::query!(
+        r#"
+UPDATE issue_deliveries
+SET
+status = 'retrying',
+last_error = $3,
+updated_at = now()
+WHERE
+status = 'sending' AND
+(newsletter_issue_id, subscriber_id) IN (
+SELECT * FROM UNNEST($1::uuid[], $2::uuid[])
+)
+"#,
+        &issue_ids,
+        &subscriber_ids,
+        last_error,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Keeps track of the outcome of every delivery, see `issue_deliveries`.
+#[tracing::instrument(skip(executor, task, message_id, last_error))]
+async fn record_delivery<'c, E>(
+    executor: E,
+    task: &Task,
+    status: &str,
+    message_id: Option<&str>,
+    last_error: Option<&str>,
+) -> Result<(), anyhow::Error>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    sqlx::query!(
+        r#"
+INSERT INTO issue_deliveries (
+newsletter_issue_id,
+subscriber_id,
+status,
+provider_message_id,
+n_attempts,
+last_error,
+created_at,
+updated_at
+)
+VALUES ($1, $2, $3, $4, $5, $6, now(), now())
+ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+SET
+status = EXCLUDED.status,
+provider_message_id = EXCLUDED.provider_message_id,
+n_attempts = EXCLUDED.n_attempts,
+last_error = EXCLUDED.last_error,
+updated_at = EXCLUDED.updated_at
+"#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        status,
+        message_id,
+        task.n_retries + 1,
+        last_error
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, task))]
+async fn reschedule_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    delay: Duration,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+UPDATE issue_delivery_queue
+SET
+n_retries = n_retries + 1,
+execute_after = now() + make_interval(secs => $3)
+WHERE
+newsletter_issue_id = $1 AND
+subscriber_id = $2
+"#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        delay.as_secs_f64()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, task))]
+async fn dead_letter_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO issue_delivery_failures (
+newsletter_issue_id,
+subscriber_id,
+n_retries,
+last_error,
+failed_at
+)
+VALUES ($1, $2, $3, $4, now())
+ON CONFLICT (newsletter_issue_id, subscriber_id) DO UPDATE
+SET
+n_retries = EXCLUDED.n_retries,
+last_error = EXCLUDED.last_error,
+failed_at = EXCLUDED.failed_at
+"#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        task.n_retries,
+        last_error
+    )
+    .execute(&mut *transaction)
+    .await?;
+    delete_tasks(transaction, &[task]).await
+}
+
+/// Moves dead-lettered deliveries back into the queue, either for a single
+/// issue or for all of them. Returns the number of requeued deliveries.
+/// Deliveries of cancelled issues stay where they are.
+#[tracing::instrument(skip(pool))]
+pub async fn requeue_failed_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+WITH requeued AS (
+DELETE FROM issue_delivery_failures
+WHERE
+($1::uuid IS NULL OR newsletter_issue_id = $1) AND
+newsletter_issue_id NOT IN (
+SELECT newsletter_issue_id FROM newsletter_issues WHERE status = 'cancelled'
+)
+RETURNING newsletter_issue_id, subscriber_id
+)
+INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id)
+SELECT newsletter_issue_id, subscriber_id
+FROM requeued
+ON CONFLICT DO NOTHING
+"#,
+        newsletter_issue_id
+    )
+    .execute(pool)
+    .await?;
+    notify_workers(pool).await?;
+    Ok(result.rows_affected())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn update_subscriber_status(
+    transaction: &mut PgTransaction,
+    subscriber_id: Uuid,
+    status: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE id = $1"#,
+        subscriber_id,
+        status
+    )
+    .execute(transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
-struct NewsletterIssue {
-    title: String,
-    text_content: String,
-    html_content: String,
+/// The content of the most recently delivered issues, so that a worker loads an issue
+/// once rather than once per batch. Issues can't change once they have been queued.
+pub struct IssueCache {
+    capacity: usize,
+    issues: HashMap<Uuid, Arc<NewsletterTemplate>>,
+    /// Least recently used first.
+    recently_used: VecDeque<Uuid>,
+}
+
+impl IssueCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            issues: HashMap::new(),
+            recently_used: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, issue_id: Uuid) -> Option<Arc<NewsletterTemplate>> {
+        let issue = self.issues.get(&issue_id)?.clone();
+        self.touch(issue_id);
+        Some(issue)
+    }
+
+    fn insert(&mut self, issue_id: Uuid, issue: Arc<NewsletterTemplate>) {
+        if self.issues.insert(issue_id, issue).is_none() && self.issues.len() > self.capacity {
+            if let Some(evicted) = self.recently_used.pop_front() {
+                self.issues.remove(&evicted);
+            }
+        }
+        self.touch(issue_id);
+    }
+
+    fn touch(&mut self, issue_id: Uuid) {
+        self.recently_used.retain(|id| *id != issue_id);
+        self.recently_used.push_back(issue_id);
+    }
+}
+
+/// Loads every issue referenced by a batch of tasks, hitting the database only for
+/// issues that are not cached yet.
+async fn get_issues(
+    pool: &PgPool,
+    cache: &mut IssueCache,
+    tasks: &[Task],
+) -> Result<HashMap<Uuid, Arc<NewsletterTemplate>>, anyhow::Error> {
+    let mut issues = HashMap::new();
+    let mut n_queries = 0;
+    for task in tasks {
+        let issue_id = task.newsletter_issue_id;
+        if let Entry::Vacant(entry) = issues.entry(issue_id) {
+            let issue = match cache.get(issue_id) {
+                Some(issue) => issue,
+                None => {
+                    n_queries += 1;
+                    let issue = Arc::new(get_issue(pool, issue_id).await?);
+                    cache.insert(issue_id, issue.clone());
+                    issue
+                }
+            };
+            entry.insert(issue);
+        }
+    }
+    Span::current().record("n_issue_queries", n_queries);
+    Ok(issues)
 }
 
 #[tracing::instrument(skip_all)]
-async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
-    let issue = sqlx::query_as!(
-        NewsletterIssue,
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterTemplate, anyhow::Error> {
+    let issue = sqlx::query!(
         r#"
 SELECT title, text_content, html_content
 FROM newsletter_issues
@@ -132,21 +680,229 @@ newsletter_issue_id = $1
     .fetch_one(pool)
     .await?;
 
-    Ok(issue)
+    Ok(NewsletterTemplate::parse_or_literal(
+        &issue.title,
+        &issue.text_content,
+        &issue.html_content,
+    ))
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
+/// Queues a delivery of the issue to every confirmed subscriber.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+INSERT INTO issue_delivery_queue (
+newsletter_issue_id,
+subscriber_id
+)
+
+SELECT $1, id
+FROM subscriptions
+WHERE status = 'confirmed'
+"#,
+        newsletter_issue_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    notify_workers(transaction).await?;
+
+    Ok(())
+}
+
+/// The channel `enqueue_delivery_tasks` notifies whenever new deliveries are queued.
+pub const QUEUE_CHANNEL: &str = "issue_delivery_queue";
+
+/// Notifies idle workers that there are deliveries waiting for them.
+/// When called inside a transaction the notification only goes out on commit.
+pub async fn notify_workers<'c, E>(executor: E) -> Result<(), sqlx::Error>
+where
+    E: sqlx::PgExecutor<'c>,
+{
+    sqlx::query!("SELECT pg_notify($1, '')", QUEUE_CHANNEL)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Runs until `shutdown` fires. We only check for it between batches, so the batch
+/// in flight is always sent and committed before the loop exits.
+#[tracing::instrument(skip(pool, email_client, settings, unsubscribe_links, shutdown))]
+async fn worker_loop(
+    worker_id: usize,
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    settings: WorkerSettings,
+    unsubscribe_links: UnsubscribeLinks,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let mut listener = listen(&pool, QUEUE_CHANNEL).await;
+    let mut issue_cache = IssueCache::new(settings.issue_cache_size);
+    while !shutdown.is_triggered() {
+        match try_execute_task(
+            &pool,
+            &email_client,
+            &settings,
+            &unsubscribe_links,
+            &mut issue_cache,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::select! {
+                    _ = wait_for_tasks(listener.as_mut(), settings.poll_interval()) => {}
+                    _ = shutdown.triggered() => {}
+                }
+            }
             Ok(ExecutionOutcome::TaskCompleted) => {}
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            Err(_) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(settings.error_backoff()) => {}
+                    _ = shutdown.triggered() => {}
+                }
+            }
         }
     }
+    tracing::info!("Delivery worker has stopped.");
+    Ok(())
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub(crate) async fn listen(pool: &PgPool, channel: &str) -> Option<PgListener> {
+    let listener = async {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(channel).await?;
+        Ok::<_, sqlx::Error>(listener)
+    };
+    match listener.await {
+        Ok(listener) => Some(listener),
+        Err(e) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to listen for queue notifications. Falling back to polling."
+            );
+            None
+        }
+    }
+}
+
+/// Waits until new tasks are announced on the channel we listen to, or until
+/// `poll_interval` has elapsed. Polling is our safety net for notifications we miss,
+/// e.g. while reconnecting.
+pub(crate) async fn wait_for_tasks(listener: Option<&mut PgListener>, poll_interval: Duration) {
+    let listener = match listener {
+        Some(listener) => listener,
+        None => return tokio::time::sleep(poll_interval).await,
+    };
+    match tokio::time::timeout(poll_interval, listener.try_recv()).await {
+        // Woken up, or the connection was lost and we might have missed a notification:
+        // either way it's time to look at the queue again.
+        Ok(Ok(_)) | Err(_) => {}
+        Ok(Err(e)) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to receive queue notifications."
+            );
+            tokio::time::sleep(poll_interval).await
+        }
+    }
+}
+
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let email_client = Arc::new(configuration.email_client.client());
+    let email_templates =
+        EmailTemplates::load(Path::new(&configuration.email_templates.directory))?;
+    // Broken overrides should stop a deployment, not just the emails that use them.
+    EmailTemplates::validate_overrides(&connection_pool).await?;
+    let outbox_renderer = OutboxRenderer::new(
+        email_templates,
+        configuration.application.base_url.clone(),
+        configuration.subscriptions.default_locale,
+    );
+    let unsubscribe_links = UnsubscribeLinks::new(
+        configuration.application.base_url,
+        configuration.application.hmac_secret,
+    );
+    let settings = configuration.worker;
+    let mut workers: Vec<_> = (0..settings.concurrency())
+        .map(|worker_id| {
+            tokio::spawn(worker_loop(
+                worker_id,
+                connection_pool.clone(),
+                email_client.clone(),
+                settings.clone(),
+                unsubscribe_links.clone(),
+                shutdown.clone(),
+            ))
+        })
+        .collect();
+    workers.push(tokio::spawn(scheduler_loop(
+        connection_pool.clone(),
+        settings.scheduler_interval(),
+        shutdown.clone(),
+    )));
+    workers.push(tokio::spawn(outbox_loop(
+        connection_pool.clone(),
+        email_client.clone(),
+        outbox_renderer,
+        settings.clone(),
+        shutdown.clone(),
+    )));
+    workers.push(tokio::spawn(cleanup_loop(
+        connection_pool.clone(),
+        configuration.subscriptions,
+        shutdown.clone(),
+    )));
+    // Bail out as soon as any worker fails, otherwise wait for all of them to stop.
+    futures::future::try_join_all(workers.into_iter().map(|worker| async { worker.await? }))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueCache, NewsletterTemplate};
+    use claim::{assert_none, assert_some};
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn issue() -> Arc<NewsletterTemplate> {
+        Arc::new(NewsletterTemplate::parse_or_literal(
+            "Title",
+            "Text",
+            "<p>HTML</p>",
+        ))
+    }
+
+    #[test]
+    fn cached_issues_are_returned() {
+        let mut cache = IssueCache::new(2);
+        let issue_id = Uuid::new_v4();
+        cache.insert(issue_id, issue());
+        assert_some!(cache.get(issue_id));
+        assert_none!(cache.get(Uuid::new_v4()));
+    }
+
+    #[test]
+    fn the_least_recently_used_issue_is_evicted_when_full() {
+        let mut cache = IssueCache::new(2);
+        let (first, second, third) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        cache.insert(first, issue());
+        cache.insert(second, issue());
+        // `first` is now more recently used than `second`
+        assert_some!(cache.get(first));
+        cache.insert(third, issue());
+
+        assert_some!(cache.get(first));
+        assert_none!(cache.get(second));
+        assert_some!(cache.get(third));
+    }
 }
@@ -2,10 +2,20 @@ pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod email_outbox;
+pub mod email_templates;
+pub mod i18n;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod newsletter_scheduler;
+pub mod newsletter_template;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
 pub mod startup;
+pub mod subscriber_import;
+pub mod subscription_cleanup;
 pub mod telemetry;
+pub mod template;
+pub mod unsubscribe;
 pub mod utils;
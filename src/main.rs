@@ -3,6 +3,7 @@ use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::shutdown;
 use zero2prod::startup::Application;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -10,13 +11,33 @@ use zero2prod::telemetry::{get_subscriber, init_subscriber};
 async fn main() -> anyhow::Result<()> {
     init_tracing();
     let configuration = get_configuration().expect("Failed to read configuration.");
+    let shutdown_timeout = configuration.application.shutdown_timeout();
+    let (trigger, mut shutdown) = shutdown::channel();
     let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let application_task = tokio::spawn(application.run_until_stopped(shutdown.clone()));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration, shutdown.clone()));
+    tokio::spawn(trigger.clone().trigger_on_signal());
+
+    // Whichever task exits first, for whatever reason, takes the other one down with it.
+    let application_task = async {
+        let o = application_task.await;
+        trigger.trigger();
+        report_exit("API", o);
+    };
+    let worker_task = async {
+        let o = worker_task.await;
+        trigger.trigger();
+        report_exit("Background worker", o);
+    };
 
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = worker_task => report_exit("Background worker", o),
+        _ = async { tokio::join!(application_task, worker_task) } => {}
+        _ = async {
+            shutdown.triggered().await;
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            tracing::warn!("Graceful shutdown timed out after {:?}", shutdown_timeout);
+        }
     };
 
     Ok(())
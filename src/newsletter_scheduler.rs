@@ -0,0 +1,56 @@
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::shutdown::Shutdown;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// Publishes every scheduled issue whose `publish_at` is due, queuing its deliveries.
+/// Returns the number of published issues.
+#[tracing::instrument(skip_all, fields(n_published = tracing::field::Empty), err)]
+pub async fn try_publish_due_issues(pool: &PgPool) -> Result<usize, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let due_issues = sqlx::query!(
+        r#"
+SELECT newsletter_issue_id
+FROM newsletter_issues
+WHERE status = 'scheduled' AND publish_at <= now()
+FOR UPDATE
+SKIP LOCKED
+"#
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+
+    for issue in &due_issues {
+        sqlx::query!(
+            r#"
+UPDATE newsletter_issues
+SET status = 'published', published_at = now()
+WHERE newsletter_issue_id = $1
+"#,
+            issue.newsletter_issue_id
+        )
+        .execute(&mut transaction)
+        .await?;
+        enqueue_delivery_tasks(&mut transaction, issue.newsletter_issue_id).await?;
+    }
+    transaction.commit().await?;
+
+    tracing::Span::current().record("n_published", due_issues.len());
+    Ok(due_issues.len())
+}
+
+pub async fn scheduler_loop(
+    pool: PgPool,
+    interval: Duration,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        // Errors have been logged already, we'll try again on the next tick.
+        let _ = try_publish_due_issues(&pool).await;
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use crate::email_templates::RenderedEmail;
+use crate::template::Template;
+use htmlescape::encode_minimal;
+
+/// The placeholders newsletter issues can use, rendered for every recipient.
+pub const ISSUE_VARIABLES: [&str; 3] = ["subscriber.name", "subscriber.email", "unsubscribe_url"];
+
+#[derive(Debug)]
+pub struct NewsletterTemplate {
+    title: Template,
+    text_content: Template,
+    html_content: Template,
+}
+
+pub struct Recipient<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+impl NewsletterTemplate {
+    pub fn parse(title: &str, text_content: &str, html_content: &str) -> Result<Self, String> {
+        let parse = |field: &str, source: &str| {
+            Template::parse(source, &ISSUE_VARIABLES)
+                .map_err(|e| format!("The {} of the newsletter issue is invalid: {}", field, e))
+        };
+        Ok(Self {
+            title: parse("title", title)?,
+            text_content: parse("plain text content", text_content)?,
+            html_content: parse("HTML content", html_content)?,
+        })
+    }
+
+    /// Issues published before placeholders existed are sent as they are.
+    pub fn parse_or_literal(title: &str, text_content: &str, html_content: &str) -> Self {
+        Self::parse(title, text_content, html_content).unwrap_or_else(|e| {
+            tracing::warn!(
+                error.message = %e,
+                "Sending a newsletter issue without rendering its placeholders."
+            );
+            Self {
+                title: Template::literal(title),
+                text_content: Template::literal(text_content),
+                html_content: Template::literal(html_content),
+            }
+        })
+    }
+
+    /// Values are HTML-escaped in the HTML content.
+    pub fn render(&self, recipient: &Recipient) -> RenderedEmail {
+        let variables = [
+            ("subscriber.name", recipient.name),
+            ("subscriber.email", recipient.email),
+            ("unsubscribe_url", recipient.unsubscribe_url),
+        ];
+        RenderedEmail {
+            subject: self.title.render(&variables, str::to_string),
+            text_body: self.text_content.render(&variables, str::to_string),
+            html_body: self.html_content.render(&variables, encode_minimal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NewsletterTemplate, Recipient};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn placeholders_are_rendered_for_each_recipient() {
+        let template = assert_ok!(NewsletterTemplate::parse(
+            "News for {{ subscriber.name }}",
+            "Sent to {{subscriber.email}}. Unsubscribe: {{unsubscribe_url}}",
+            r#"<a href="{{unsubscribe_url}}">Bye {{subscriber.name}}</a>"#,
+        ));
+
+        let rendered = template.render(&Recipient {
+            name: "Le Guin & co",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/u?a=1&b=2",
+        });
+
+        assert_eq!(rendered.subject, "News for Le Guin & co");
+        assert_eq!(
+            rendered.text_body,
+            "Sent to ursula@example.com. Unsubscribe: https://example.com/u?a=1&b=2"
+        );
+        assert_eq!(
+            rendered.html_body,
+            r#"<a href="https://example.com/u?a=1&amp;b=2">Bye Le Guin &amp; co</a>"#
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let e = assert_err!(NewsletterTemplate::parse(
+            "Title",
+            "Hi {{subscriber.age}}",
+            ""
+        ));
+        assert_eq!(
+            e,
+            "The plain text content of the newsletter issue is invalid: \
+            `subscriber.age` is not a known variable"
+        );
+    }
+
+    #[test]
+    fn invalid_legacy_issues_are_sent_as_they_are() {
+        let template = NewsletterTemplate::parse_or_literal("{{title}}", "{{", "<p>{{x}}</p>");
+
+        let rendered = template.render(&Recipient {
+            name: "",
+            email: "",
+            unsubscribe_url: "",
+        });
+
+        assert_eq!(rendered.subject, "{{title}}");
+        assert_eq!(rendered.text_body, "{{");
+        assert_eq!(rendered.html_body, "<p>{{x}}</p>");
+    }
+}
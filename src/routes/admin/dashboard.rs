@@ -33,7 +33,12 @@ pub async fn admin_dashboard(
 
 <p>Available actions:</p>
 <ol>
+<li><a href="/admin/newsletters">Publish a newsletter issue</a></li>
+<li><a href="/admin/newsletters/scheduled">Scheduled newsletter issues</a></li>
+<li><a href="/admin/newsletters/sending">Newsletter issues being delivered</a></li>
 <li><a href="/admin/password">Change password</a></li>
+<li><a href="/admin/deliveries/failures">Failed deliveries</a></li>
+<li><a href="/admin/subscribers">Subscribers</a></li>
 <li>
 <form name="logoutForm" action="/admin/logout" method="post">
 <input type="submit" value="Logout">
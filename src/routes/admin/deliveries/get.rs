@@ -0,0 +1,183 @@
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct FailedDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+struct FailedEmail {
+    subscriber_id: Uuid,
+    subscriber_email: String,
+    kind: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+pub async fn failed_deliveries(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let failures = get_failed_deliveries(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for f in &failures {
+        writeln!(
+            rows_html,
+            r#"<tr>
+<td>{title}</td>
+<td>{email}</td>
+<td>{attempts}</td>
+<td>{failed_at}</td>
+<td>{last_error}</td>
+<td>
+<form action="/admin/deliveries/failures/requeue" method="post">
+<input hidden type="text" name="newsletter_issue_id" value="{issue_id}">
+<button type="submit">Requeue issue</button>
+</form>
+</td>
+</tr>"#,
+            title = encode_minimal(&f.title),
+            email = encode_minimal(&f.subscriber_email),
+            attempts = f.n_retries + 1,
+            failed_at = f.failed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            last_error = encode_minimal(&f.last_error),
+            issue_id = f.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    let failed_emails = get_failed_emails(&pool).await.map_err(e500)?;
+    let mut emails_html = String::new();
+    for f in &failed_emails {
+        writeln!(
+            emails_html,
+            r#"<tr>
+<td><a href="/admin/subscribers/{subscriber_id}">{email}</a></td>
+<td>{kind}</td>
+<td>{attempts}</td>
+<td>{failed_at}</td>
+<td>{last_error}</td>
+</tr>"#,
+            subscriber_id = f.subscriber_id,
+            email = encode_minimal(&f.subscriber_email),
+            kind = encode_minimal(&f.kind),
+            attempts = f.n_retries + 1,
+            failed_at = f.failed_at.format("%Y-%m-%d %H:%M:%S UTC"),
+            last_error = encode_minimal(&f.last_error),
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Failed deliveries</title>
+</head>
+<body>
+{msg_html}
+<p>{n_failures} deliveries failed permanently.</p>
+<table>
+<tr>
+<th>Issue</th>
+<th>Subscriber</th>
+<th>Attempts</th>
+<th>Failed at</th>
+<th>Last error</th>
+<th></th>
+</tr>
+{rows_html}
+</table>
+<form action="/admin/deliveries/failures/requeue" method="post">
+<button type="submit">Requeue all</button>
+</form>
+<h2>Subscription emails</h2>
+<p>{n_failed_emails} emails failed permanently.</p>
+<table>
+<tr>
+<th>Subscriber</th>
+<th>Email</th>
+<th>Attempts</th>
+<th>Failed at</th>
+<th>Last error</th>
+</tr>
+{emails_html}
+</table>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            n_failures = failures.len(),
+            n_failed_emails = failed_emails.len(),
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+SELECT
+f.newsletter_issue_id,
+i.title,
+s.email AS subscriber_email,
+f.n_retries,
+f.last_error,
+f.failed_at
+FROM issue_delivery_failures f
+JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+JOIN subscriptions s ON s.id = f.subscriber_id
+ORDER BY f.failed_at DESC
+"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed deliveries")?;
+
+    Ok(failures)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_failed_emails(pool: &PgPool) -> Result<Vec<FailedEmail>, anyhow::Error> {
+    let failures = sqlx::query_as!(
+        FailedEmail,
+        r#"
+SELECT
+f.subscriber_id,
+s.email AS subscriber_email,
+f.kind,
+f.n_retries,
+f.last_error,
+f.failed_at
+FROM email_outbox_failures f
+JOIN subscriptions s ON s.id = f.subscriber_id
+ORDER BY f.failed_at DESC
+"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed subscription emails")?;
+
+    Ok(failures)
+}
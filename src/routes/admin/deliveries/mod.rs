@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::failed_deliveries;
+pub use post::requeue_failed_deliveries;
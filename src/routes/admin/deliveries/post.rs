@@ -0,0 +1,25 @@
+use crate::issue_delivery_worker;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    /// Requeue every failed delivery when missing.
+    newsletter_issue_id: Option<Uuid>,
+}
+
+#[tracing::instrument(name = "Requeue failed deliveries", skip(pool, form))]
+pub async fn requeue_failed_deliveries(
+    pool: web::Data<PgPool>,
+    form: web::Form<FormData>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let n_requeued =
+        issue_delivery_worker::requeue_failed_deliveries(&pool, form.0.newsletter_issue_id)
+            .await
+            .map_err(e500)?;
+    FlashMessage::info(format!("{} deliveries have been requeued.", n_requeued)).send();
+    Ok(see_other("/admin/deliveries/failures"))
+}
@@ -1,9 +1,13 @@
 mod dashboard;
+mod deliveries;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
 
 pub use dashboard::admin_dashboard;
+pub use deliveries::*;
 pub use logout::log_out;
 pub use newsletter::*;
 pub use password::*;
+pub use subscribers::*;
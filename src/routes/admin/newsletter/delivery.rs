@@ -0,0 +1,143 @@
+use crate::issue_delivery_worker::notify_workers;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Stops the delivery of a published issue. Batches that a worker has already picked
+/// up still go out.
+#[tracing::instrument(name = "Pause a newsletter issue delivery", skip(pool))]
+pub async fn pause_newsletter(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let updated = update_issue_status(&pool, issue_id.into_inner(), "published", "paused")
+        .await
+        .map_err(e500)?;
+    if updated {
+        FlashMessage::info("The newsletter issue delivery has been paused.").send();
+    } else {
+        FlashMessage::error("Only newsletter issues that are being delivered can be paused.")
+            .send();
+    }
+    Ok(see_other("/admin/newsletters/sending"))
+}
+
+#[tracing::instrument(name = "Resume a newsletter issue delivery", skip(pool))]
+pub async fn resume_newsletter(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let updated = update_issue_status(&pool, issue_id.into_inner(), "paused", "published")
+        .await
+        .map_err(e500)?;
+    if updated {
+        notify_workers(pool.get_ref())
+            .await
+            .context("Failed to notify the delivery workers")
+            .map_err(e500)?;
+        FlashMessage::info("The newsletter issue delivery has been resumed.").send();
+    } else {
+        FlashMessage::error("Only paused newsletter issues can be resumed.").send();
+    }
+    Ok(see_other("/admin/newsletters/sending"))
+}
+
+/// Cancels a scheduled issue, or the remaining deliveries of a published one.
+#[tracing::instrument(name = "Cancel a newsletter issue", skip(pool))]
+pub async fn cancel_newsletter(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let previous_status = sqlx::query!(
+        r#"
+WITH previous AS (
+SELECT newsletter_issue_id, status
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1
+FOR UPDATE
+)
+UPDATE newsletter_issues i
+SET status = 'cancelled'
+FROM previous
+WHERE
+i.newsletter_issue_id = previous.newsletter_issue_id AND
+previous.status IN ('scheduled', 'published', 'paused')
+RETURNING previous.status
+"#,
+        issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to cancel the newsletter issue")
+    .map_err(e500)?
+    .map(|r| r.status);
+    // Waits for the batches workers are sending right now. Failed deliveries are kept
+    // for the record, they can't be requeued once the issue is cancelled.
+    if previous_status.is_some() {
+        sqlx::query!(
+            r#"DELETE FROM issue_delivery_queue WHERE newsletter_issue_id = $1"#,
+            issue_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to remove the remaining deliveries")
+        .map_err(e500)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the cancellation")
+        .map_err(e500)?;
+
+    match previous_status.as_deref() {
+        Some("scheduled") => {
+            FlashMessage::info("The newsletter issue has been cancelled.").send();
+            Ok(see_other("/admin/newsletters/scheduled"))
+        }
+        Some(_) => {
+            FlashMessage::info(
+                "The remaining deliveries of the newsletter issue have been cancelled.",
+            )
+            .send();
+            Ok(see_other("/admin/newsletters/sending"))
+        }
+        None => {
+            FlashMessage::error("The newsletter issue can no longer be cancelled.").send();
+            Ok(see_other("/admin/newsletters/sending"))
+        }
+    }
+}
+
+/// Returns whether the issue was in the `from` status.
+#[tracing::instrument(skip(pool))]
+async fn update_issue_status(
+    pool: &PgPool,
+    issue_id: Uuid,
+    from: &str,
+    to: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET status = $3
+WHERE newsletter_issue_id = $1 AND status = $2
+"#,
+        issue_id,
+        from,
+        to
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the status of the newsletter issue")?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,146 @@
+use super::post::{parse_publish_at, PUBLISH_AT_FORMAT};
+use crate::newsletter_template::NewsletterTemplate;
+use crate::utils::{e400, e500, see_other};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ScheduledIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+    publish_at: DateTime<Utc>,
+}
+
+pub async fn edit_newsletter_form(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = match get_scheduled_issue(&pool, issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => {
+            FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+            return Ok(see_other("/admin/newsletters/scheduled"));
+        }
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Edit scheduled newsletter issue</title>
+</head>
+<body>
+{msg_html}
+<form action="/admin/newsletters/{issue_id}/edit" method="post">
+<label>Title:<br>
+<input type="text" name="title" value="{title}">
+</label>
+<br>
+<label>Plain text content:<br>
+<textarea name="text_content" rows="20" cols="50">{text_content}</textarea>
+</label>
+<br>
+<label>HTML content:<br>
+<textarea name="html_content" rows="20" cols="50">{html_content}</textarea>
+</label>
+<br>
+<label>Publish at (UTC):<br>
+<input type="datetime-local" name="publish_at" value="{publish_at}">
+</label>
+<br>
+<button type="submit">Save</button>
+</form>
+<p><a href="/admin/newsletters/scheduled">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            title = encode_attribute(&issue.title),
+            text_content = encode_minimal(&issue.text_content),
+            html_content = encode_minimal(&issue.html_content),
+            publish_at = issue.publish_at.format(PUBLISH_AT_FORMAT),
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+    publish_at: String,
+}
+
+#[tracing::instrument(name = "Edit a scheduled newsletter issue", skip(pool, form))]
+pub async fn edit_newsletter(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+    form: web::Form<FormData>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let publish_at = parse_publish_at(form.publish_at.trim()).map_err(e400)?;
+    NewsletterTemplate::parse(&form.title, &form.text_content, &form.html_content).map_err(e400)?;
+    let result = sqlx::query!(
+        r#"
+UPDATE newsletter_issues
+SET
+title = $2,
+text_content = $3,
+html_content = $4,
+publish_at = $5
+WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+"#,
+        issue_id,
+        form.title,
+        form.text_content,
+        form.html_content,
+        publish_at
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the scheduled newsletter issue")
+    .map_err(e500)?;
+
+    if result.rows_affected() == 0 {
+        FlashMessage::error("The newsletter issue is no longer scheduled.").send();
+    } else {
+        FlashMessage::info("The scheduled newsletter issue has been updated.").send();
+    }
+    Ok(see_other("/admin/newsletters/scheduled"))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_scheduled_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<ScheduledIssue>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+SELECT title, text_content, html_content, publish_at AS "publish_at!"
+FROM newsletter_issues
+WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+"#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the scheduled newsletter issue")?;
+
+    Ok(issue)
+}
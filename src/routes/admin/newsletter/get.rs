@@ -1,3 +1,4 @@
+use crate::newsletter_template::ISSUE_VARIABLES;
 use actix_web::{http::header::ContentType, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
 use std::fmt::Write;
@@ -11,6 +12,11 @@ pub async fn publish_newsletter_form(
     }
 
     let idempotency_key = uuid::Uuid::new_v4();
+    let placeholders = ISSUE_VARIABLES
+        .iter()
+        .map(|v| format!("<code>{{{{{}}}}}</code>", v))
+        .collect::<Vec<_>>()
+        .join(", ");
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -24,6 +30,7 @@ pub async fn publish_newsletter_form(
 </head>
 <body>
     {msg_html}
+    <p>The title and contents can use {placeholders}, filled in for every subscriber.</p>
     <form action="/admin/newsletters" method="post">
         <label>Title:<br>
             <input
@@ -50,10 +57,15 @@ pub async fn publish_newsletter_form(
                 cols="50"
             ></textarea>
         </label>
-        <br
+        <br>
+        <label>Publish at (UTC, leave empty to publish right away):<br>
+            <input type="datetime-local" name="publish_at">
+        </label>
+        <br>
         <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
         <button type="submit">Publish</button>
     </form>
+    <p><a href="/admin/newsletters/scheduled">Scheduled issues</a></p>
     <p><a href="/admin/dashboard">&lt;- Back</a></p>
 </body>
 </html>"#,
@@ -1,5 +1,15 @@
+mod delivery;
+mod edit;
 mod get;
 mod post;
+mod progress;
+mod scheduled;
+mod sending;
 
+pub use delivery::{cancel_newsletter, pause_newsletter, resume_newsletter};
+pub use edit::{edit_newsletter, edit_newsletter_form};
 pub use get::publish_newsletter_form;
 pub use post::publish_newsletter;
+pub use progress::newsletter_progress;
+pub use scheduled::scheduled_newsletters;
+pub use sending::sending_newsletters;
@@ -2,11 +2,14 @@ use crate::idempotency::save_response;
 use crate::idempotency::try_processing;
 use crate::idempotency::IdempotencyKey;
 use crate::idempotency::NextAction;
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::newsletter_template::NewsletterTemplate;
 use crate::utils::{e400, e500};
 use crate::{authentication::UserId, utils::see_other};
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
@@ -16,6 +19,8 @@ pub struct FormData {
     text_content: String,
     html_content: String,
     idempotency_key: String,
+    /// Publish right away when missing or blank.
+    publish_at: Option<String>,
 }
 
 #[tracing::instrument(
@@ -34,39 +39,79 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
+        publish_at,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    // Placeholders are rendered by the worker, mistakes have to be caught before that.
+    NewsletterTemplate::parse(&title, &text_content, &html_content).map_err(e400)?;
+    let publish_at = match publish_at.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(publish_at) => Some(parse_publish_at(publish_at).map_err(e400)?),
+    }
+    // A date in the past means "right now".
+    .filter(|publish_at| *publish_at > Utc::now());
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
         NextAction::StartProcessing(t) => t,
         NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
+            success_message(publish_at).send();
             return Ok(saved_response);
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &title,
+        &text_content,
+        &html_content,
+        publish_at,
+    )
+    .await
+    .context("Failed to store newsletter issue details")
+    .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
-        .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+    if publish_at.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
 
-    let response = see_other("/admin/newsletters");
+    // Immediate deliveries can be followed on the progress page.
+    let response = match publish_at {
+        Some(_) => see_other("/admin/newsletters"),
+        None => see_other(&format!("/admin/newsletters/{}", issue_id)),
+    };
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
-    success_message().send();
+    success_message(publish_at).send();
     Ok(response)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
+fn success_message(publish_at: Option<DateTime<Utc>>) -> FlashMessage {
+    match publish_at {
+        Some(publish_at) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled for {}.",
+            publish_at.format(PUBLISH_AT_DISPLAY_FORMAT)
+        )),
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted - emails will go out shortly.",
+        ),
+    }
+}
+
+/// How `datetime-local` inputs submit their value. Times are always in UTC.
+pub const PUBLISH_AT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+pub const PUBLISH_AT_DISPLAY_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+pub fn parse_publish_at(s: &str) -> Result<DateTime<Utc>, String> {
+    NaiveDateTime::parse_from_str(s, PUBLISH_AT_FORMAT)
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S"))
+        .map(|publish_at| DateTime::from_utc(publish_at, Utc))
+        .map_err(|_| format!("{} is not a valid publishing date.", s))
 }
 
 #[tracing::instrument(skip_all)]
@@ -75,6 +120,7 @@ async fn insert_newsletter_issue(
     title: &str,
     text_content: &str,
     html_content: &str,
+    publish_at: Option<DateTime<Utc>>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -84,41 +130,25 @@ newsletter_issue_id,
 title,
 text_content,
 html_content,
+status,
+publish_at,
 published_at
 )
-VALUES ($1, $2, $3, $4, now())
+VALUES (
+$1, $2, $3, $4,
+CASE WHEN $5::timestamptz IS NULL THEN 'published' ELSE 'scheduled' END,
+$5,
+CASE WHEN $5::timestamptz IS NULL THEN now() END
+)
 "#,
         newsletter_issue_id,
         title,
         text_content,
         html_content,
+        publish_at,
     )
     .execute(transaction)
     .await?;
 
     Ok(newsletter_issue_id)
 }
-
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-INSERT INTO issue_delivery_queue (
-newsletter_issue_id,
-subscriber_email
-)
-
-SELECT $1, email
-FROM subscriptions
-WHERE status = 'confirmed'
-"#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
-
-    Ok(())
-}
@@ -0,0 +1,137 @@
+use super::post::PUBLISH_AT_DISPLAY_FORMAT;
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct IssueProgress {
+    title: String,
+    status: String,
+    n_remaining: i64,
+    n_sent: i64,
+    n_failed: i64,
+    n_skipped: i64,
+    started_at: Option<DateTime<Utc>>,
+    last_delivery_at: Option<DateTime<Utc>>,
+}
+
+impl IssueProgress {
+    fn n_recipients(&self) -> i64 {
+        self.n_remaining + self.n_sent + self.n_failed + self.n_skipped
+    }
+
+    fn finished_at(&self) -> Option<DateTime<Utc>> {
+        if self.status == "published" && self.n_remaining == 0 {
+            self.last_delivery_at
+        } else {
+            None
+        }
+    }
+}
+
+pub async fn newsletter_progress(
+    pool: web::Data<PgPool>,
+    issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let progress = get_issue_progress(&pool, issue_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown newsletter issue."))?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+    let format_time = |time: Option<DateTime<Utc>>| match time {
+        Some(time) => time.format(PUBLISH_AT_DISPLAY_FORMAT).to_string(),
+        None => "-".into(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Newsletter issue delivery</title>
+</head>
+<body>
+{msg_html}
+<h1>{title}</h1>
+<table>
+<tr><th>Status</th><td>{status}</td></tr>
+<tr><th>Recipients</th><td>{n_recipients}</td></tr>
+<tr><th>Remaining</th><td>{n_remaining}</td></tr>
+<tr><th>Sent</th><td>{n_sent}</td></tr>
+<tr><th>Failed</th><td>{n_failed}</td></tr>
+<tr><th>Skipped</th><td>{n_skipped}</td></tr>
+<tr><th>Started at</th><td>{started_at}</td></tr>
+<tr><th>Finished at</th><td>{finished_at}</td></tr>
+</table>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            title = encode_minimal(&progress.title),
+            status = progress.status,
+            n_recipients = progress.n_recipients(),
+            n_remaining = progress.n_remaining,
+            n_sent = progress.n_sent,
+            n_failed = progress.n_failed,
+            n_skipped = progress.n_skipped,
+            started_at = format_time(progress.started_at),
+            finished_at = format_time(progress.finished_at()),
+        )))
+}
+
+/// Failed deliveries, and those whose outcome is unknown, count as remaining once
+/// requeued.
+#[tracing::instrument(skip(pool))]
+async fn get_issue_progress(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueProgress>, anyhow::Error> {
+    let progress = sqlx::query_as!(
+        IssueProgress,
+        r#"
+SELECT
+i.title,
+i.status,
+(
+SELECT COUNT(*) FROM issue_delivery_queue q
+WHERE q.newsletter_issue_id = i.newsletter_issue_id
+) AS "n_remaining!",
+COUNT(d.subscriber_id) FILTER (WHERE d.status = 'sent') AS "n_sent!",
+COUNT(d.subscriber_id) FILTER (
+WHERE d.status IN ('failed', 'unknown') AND NOT EXISTS (
+SELECT 1 FROM issue_delivery_queue q
+WHERE q.newsletter_issue_id = i.newsletter_issue_id AND q.subscriber_id = d.subscriber_id
+)
+) AS "n_failed!",
+COUNT(d.subscriber_id) FILTER (
+WHERE d.status IN ('skipped_invalid', 'skipped_unsubscribed')
+) AS "n_skipped!",
+MIN(d.created_at) AS started_at,
+MAX(d.updated_at) AS last_delivery_at
+FROM newsletter_issues i
+LEFT JOIN issue_deliveries d ON d.newsletter_issue_id = i.newsletter_issue_id
+WHERE i.newsletter_issue_id = $1
+GROUP BY i.newsletter_issue_id
+"#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the delivery progress of the newsletter issue")?;
+
+    Ok(progress)
+}
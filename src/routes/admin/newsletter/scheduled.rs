@@ -0,0 +1,95 @@
+use super::post::PUBLISH_AT_DISPLAY_FORMAT;
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct ScheduledIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    publish_at: DateTime<Utc>,
+}
+
+pub async fn scheduled_newsletters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let issues = get_scheduled_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        writeln!(
+            rows_html,
+            r#"<tr>
+<td>{title}</td>
+<td>{publish_at}</td>
+<td><a href="/admin/newsletters/{issue_id}/edit">Edit</a></td>
+<td>
+<form action="/admin/newsletters/{issue_id}/cancel" method="post">
+<button type="submit">Cancel</button>
+</form>
+</td>
+</tr>"#,
+            title = encode_minimal(&issue.title),
+            publish_at = issue.publish_at.format(PUBLISH_AT_DISPLAY_FORMAT),
+            issue_id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Scheduled newsletter issues</title>
+</head>
+<body>
+{msg_html}
+<p>{n_issues} issues are scheduled.</p>
+<table>
+<tr>
+<th>Title</th>
+<th>Publish at</th>
+<th></th>
+<th></th>
+</tr>
+{rows_html}
+</table>
+<p><a href="/admin/newsletters">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            n_issues = issues.len(),
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_scheduled_issues(pool: &PgPool) -> Result<Vec<ScheduledIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        ScheduledIssue,
+        r#"
+SELECT newsletter_issue_id, title, publish_at AS "publish_at!"
+FROM newsletter_issues
+WHERE status = 'scheduled'
+ORDER BY publish_at
+"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve scheduled newsletter issues")?;
+
+    Ok(issues)
+}
@@ -0,0 +1,109 @@
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct SendingIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    n_remaining: i64,
+}
+
+/// Lists the issues that still have deliveries in the queue.
+pub async fn sending_newsletters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let issues = get_sending_issues(&pool).await.map_err(e500)?;
+    let mut rows_html = String::new();
+    for issue in &issues {
+        let (action, label) = if issue.status == "paused" {
+            ("resume", "Resume")
+        } else {
+            ("pause", "Pause")
+        };
+        writeln!(
+            rows_html,
+            r#"<tr>
+<td><a href="/admin/newsletters/{issue_id}">{title}</a></td>
+<td>{status}</td>
+<td>{n_remaining}</td>
+<td>
+<form action="/admin/newsletters/{issue_id}/{action}" method="post">
+<button type="submit">{label}</button>
+</form>
+</td>
+<td>
+<form action="/admin/newsletters/{issue_id}/cancel" method="post">
+<button type="submit">Cancel</button>
+</form>
+</td>
+</tr>"#,
+            title = encode_minimal(&issue.title),
+            status = issue.status,
+            n_remaining = issue.n_remaining,
+            issue_id = issue.newsletter_issue_id,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Newsletter issues being delivered</title>
+</head>
+<body>
+{msg_html}
+<p>{n_issues} issues are being delivered.</p>
+<table>
+<tr>
+<th>Title</th>
+<th>Status</th>
+<th>Remaining deliveries</th>
+<th></th>
+<th></th>
+</tr>
+{rows_html}
+</table>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            n_issues = issues.len(),
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_sending_issues(pool: &PgPool) -> Result<Vec<SendingIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        SendingIssue,
+        r#"
+SELECT i.newsletter_issue_id, i.title, i.status, COUNT(*) AS "n_remaining!"
+FROM newsletter_issues i
+JOIN issue_delivery_queue q ON q.newsletter_issue_id = i.newsletter_issue_id
+WHERE i.status IN ('published', 'paused')
+GROUP BY i.newsletter_issue_id
+ORDER BY i.published_at
+"#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the newsletter issues being delivered")?;
+
+    Ok(issues)
+}
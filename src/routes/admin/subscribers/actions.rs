@@ -0,0 +1,131 @@
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Skips double opt-in, e.g. for people who asked to be added over another channel.
+#[tracing::instrument(name = "Confirm a subscriber manually", skip(pool))]
+pub async fn confirm_subscriber_manually(
+    pool: web::Data<PgPool>,
+    subscriber_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to confirm the subscriber")
+    .map_err(e500)?
+    .rows_affected()
+        > 0;
+    // Their pending confirmation link has no purpose anymore.
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the confirmation tokens of the subscriber")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber")
+        .map_err(e500)?;
+
+    Ok(redirect_after_update(subscriber_id, updated, "confirmed"))
+}
+
+#[tracing::instrument(name = "Unsubscribe a subscriber manually", skip(pool))]
+pub async fn unsubscribe_subscriber_manually(
+    pool: web::Data<PgPool>,
+    subscriber_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let updated = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1"#,
+        subscriber_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to unsubscribe the subscriber")
+    .map_err(e500)?
+    .rows_affected()
+        > 0;
+
+    Ok(redirect_after_update(
+        subscriber_id,
+        updated,
+        "unsubscribed",
+    ))
+}
+
+fn redirect_after_update(subscriber_id: Uuid, updated: bool, status: &str) -> HttpResponse {
+    if updated {
+        FlashMessage::info(format!("The subscriber has been {}.", status)).send();
+        see_other(&format!("/admin/subscribers/{}", subscriber_id))
+    } else {
+        FlashMessage::error("The subscriber no longer exists.").send();
+        see_other("/admin/subscribers")
+    }
+}
+
+/// Removes every trace of the subscriber, their delivery history included.
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    pool: web::Data<PgPool>,
+    subscriber_id: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the confirmation tokens of the subscriber")
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"DELETE FROM issue_deliveries WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the deliveries of the subscriber")
+    .map_err(e500)?;
+    // Queued deliveries, emails and their failures go along, see the
+    // `ON DELETE CASCADE` clauses.
+    let deleted = sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscriber")
+        .map_err(e500)?
+        .rows_affected()
+        > 0;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber")
+        .map_err(e500)?;
+
+    if deleted {
+        FlashMessage::info("The subscriber has been deleted.").send();
+    } else {
+        FlashMessage::error("The subscriber no longer exists.").send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
@@ -0,0 +1,181 @@
+use crate::utils::e500;
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+struct Subscriber {
+    email: String,
+    name: String,
+    status: String,
+    locale: String,
+    subscribed_at: DateTime<Utc>,
+    consent_source: Option<String>,
+}
+
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    n_attempts: i16,
+    last_error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M UTC";
+
+pub async fn subscriber_details(
+    pool: web::Data<PgPool>,
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = get_subscriber(&pool, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| actix_web::error::ErrorNotFound("Unknown subscriber."))?;
+    let deliveries = get_deliveries(&pool, subscriber_id).await.map_err(e500)?;
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut rows_html = String::new();
+    for d in &deliveries {
+        writeln!(
+            rows_html,
+            r#"<tr>
+<td><a href="/admin/newsletters/{issue_id}">{title}</a></td>
+<td>{status}</td>
+<td>{attempts}</td>
+<td>{updated_at}</td>
+<td>{last_error}</td>
+</tr>"#,
+            issue_id = d.newsletter_issue_id,
+            title = encode_minimal(&d.title),
+            status = d.status,
+            attempts = d.n_attempts,
+            updated_at = d.updated_at.format(TIME_FORMAT),
+            last_error = encode_minimal(d.last_error.as_deref().unwrap_or("")),
+        )
+        .unwrap();
+    }
+
+    let action = |action: &str, label: &str| {
+        format!(
+            r#"<form action="/admin/subscribers/{}/{}" method="post">
+<button type="submit">{}</button>
+</form>"#,
+            subscriber_id, action, label
+        )
+    };
+    let mut actions_html = String::new();
+    if subscriber.status != "confirmed" {
+        actions_html.push_str(&action("confirm", "Confirm"));
+    }
+    if subscriber.status != "unsubscribed" {
+        actions_html.push_str(&action("unsubscribe", "Unsubscribe"));
+    }
+    actions_html.push_str(&action("delete", "Delete"));
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Subscriber</title>
+</head>
+<body>
+{msg_html}
+<table>
+<tr><th>Email</th><td>{email}</td></tr>
+<tr><th>Name</th><td>{name}</td></tr>
+<tr><th>Status</th><td>{status}</td></tr>
+<tr><th>Locale</th><td>{locale}</td></tr>
+<tr><th>Subscribed at</th><td>{subscribed_at}</td></tr>
+<tr><th>Consent</th><td>{consent_source}</td></tr>
+</table>
+{actions_html}
+<h2>Deliveries</h2>
+<table>
+<tr>
+<th>Issue</th>
+<th>Status</th>
+<th>Attempts</th>
+<th>Last update</th>
+<th>Last error</th>
+</tr>
+{rows_html}
+</table>
+<p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            email = encode_minimal(&subscriber.email),
+            name = encode_minimal(&subscriber.name),
+            status = subscriber.status,
+            locale = subscriber.locale,
+            subscribed_at = subscriber.subscribed_at.format(TIME_FORMAT),
+            // Only recorded for subscribers imported as confirmed.
+            consent_source = encode_minimal(subscriber.consent_source.as_deref().unwrap_or("-")),
+        )))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        Subscriber,
+        r#"
+SELECT email, name, status, locale, subscribed_at, consent_source
+FROM subscriptions
+WHERE id = $1
+"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber")?;
+
+    Ok(subscriber)
+}
+
+/// Most recent first.
+#[tracing::instrument(skip(pool))]
+async fn get_deliveries(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Delivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        Delivery,
+        r#"
+SELECT
+d.newsletter_issue_id,
+i.title,
+d.status,
+d.n_attempts,
+d.last_error,
+d.updated_at
+FROM issue_deliveries d
+JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+WHERE d.subscriber_id = $1
+ORDER BY d.updated_at DESC
+"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the deliveries of the subscriber")?;
+
+    Ok(deliveries)
+}
@@ -0,0 +1,158 @@
+use crate::configuration::SubscriptionSettings;
+use crate::subscriber_import::{import_subscribers, ImportError, ImportMode, RowOutcome};
+use crate::utils::{e500, see_other};
+use actix_multipart::form::{bytes::Bytes, text::Text, MultipartForm};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use htmlescape::encode_minimal;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+pub async fn import_subscribers_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <p>The CSV file needs a header with <code>email</code> and <code>name</code> columns,
+    and can have a <code>locale</code> one.</p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <label>CSV file:<br>
+            <input type="file" name="file" accept=".csv,text/csv">
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="pending" checked>
+            Send them a confirmation email
+        </label>
+        <br>
+        <label>
+            <input type="radio" name="mode" value="confirmed">
+            They are already confirmed
+        </label>
+        <br>
+        <label>Where they gave their consent, for confirmed subscribers:<br>
+            <input
+                type="text"
+                placeholder="e.g. Double opt-in on our previous newsletter tool"
+                name="consent_source"
+            >
+        </label>
+        <br>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(MultipartForm)]
+pub struct ImportForm {
+    file: Bytes,
+    mode: Text<String>,
+    consent_source: Option<Text<String>>,
+}
+
+#[tracing::instrument(
+    name = "Import subscribers from a CSV file",
+    skip_all,
+    fields(file_name = ?form.file.file_name, mode = %form.mode.as_str())
+)]
+pub async fn upload_subscribers(
+    form: MultipartForm<ImportForm>,
+    pool: web::Data<PgPool>,
+    subscriptions: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let ImportForm {
+        file,
+        mode,
+        consent_source,
+    } = form.into_inner();
+    let consent_source = consent_source
+        .map(|c| c.into_inner().trim().to_owned())
+        .filter(|c| !c.is_empty());
+    let mode = match (mode.as_str(), consent_source) {
+        ("pending", _) => ImportMode::Pending,
+        ("confirmed", Some(consent_source)) => ImportMode::Confirmed { consent_source },
+        ("confirmed", None) => {
+            FlashMessage::error(
+                "Tell where confirmed subscribers gave their consent to receive the newsletter.",
+            )
+            .send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+        (mode, _) => {
+            FlashMessage::error(format!("{} is not an import mode.", mode)).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let report =
+        match import_subscribers(&pool, subscriptions.default_locale, &mode, &file.data).await {
+            Ok(report) => report,
+            Err(ImportError::InvalidFile(e)) => {
+                FlashMessage::error(encode_minimal(&e)).send();
+                return Ok(see_other("/admin/subscribers/import"));
+            }
+            Err(e) => return Err(e500(e)),
+        };
+
+    let mut rows_html = String::new();
+    for row in &report.rows {
+        let outcome = match &row.outcome {
+            RowOutcome::Accepted => "Accepted".to_owned(),
+            RowOutcome::Rejected(reason) => format!("Rejected: {}", encode_minimal(reason)),
+        };
+        writeln!(
+            rows_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            row.line,
+            encode_minimal(&row.email),
+            outcome
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Import report</title>
+</head>
+<body>
+<p>{n_accepted} accepted, {n_rejected} rejected.</p>
+<table>
+<tr>
+<th>Line</th>
+<th>Email</th>
+<th>Outcome</th>
+</tr>
+{rows_html}
+</table>
+<p><a href="/admin/subscribers">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            n_accepted = report.n_accepted(),
+            n_rejected = report.n_rejected(),
+        )))
+}
@@ -0,0 +1,208 @@
+use crate::utils::{e400, e500};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use htmlescape::{encode_attribute, encode_minimal};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+pub const SUBSCRIBER_STATUSES: [&str; 5] = [
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "inactive",
+    "invalid",
+];
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    /// Matches part of the email or of the name, ignoring case.
+    #[serde(default)]
+    search: String,
+    /// All statuses when blank.
+    #[serde(default)]
+    status: String,
+    /// Starts at 1.
+    page: Option<i64>,
+}
+
+struct SubscriberRow {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+pub async fn list_subscribers(
+    pool: web::Data<PgPool>,
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let search = query.search.trim();
+    let status = query.status.trim();
+    if !status.is_empty() && !SUBSCRIBER_STATUSES.contains(&status) {
+        return Err(e400(format!("{} is not a subscriber status.", status)));
+    }
+    let page = query.page.unwrap_or(1).max(1);
+
+    let mut msg_html = String::new();
+    for m in flash_messages.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let (subscribers, n_subscribers) = get_subscribers(&pool, search, status, page)
+        .await
+        .map_err(e500)?;
+    let mut rows_html = String::new();
+    for s in &subscribers {
+        writeln!(
+            rows_html,
+            r#"<tr>
+<td><a href="/admin/subscribers/{id}">{email}</a></td>
+<td>{name}</td>
+<td>{status}</td>
+<td>{subscribed_at}</td>
+</tr>"#,
+            id = s.id,
+            email = encode_minimal(&s.email),
+            name = encode_minimal(&s.name),
+            status = s.status,
+            subscribed_at = s.subscribed_at.format("%Y-%m-%d %H:%M UTC"),
+        )
+        .unwrap();
+    }
+
+    let mut status_options = String::from(r#"<option value="">All</option>"#);
+    for s in SUBSCRIBER_STATUSES {
+        let selected = if s == status { " selected" } else { "" };
+        write!(
+            status_options,
+            r#"<option value="{s}"{selected}>{s}</option>"#
+        )
+        .unwrap();
+    }
+
+    let n_pages = ((n_subscribers + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page_link = |page: i64| {
+        format!(
+            "/admin/subscribers?search={}&status={}&page={}",
+            urlencoding::encode(search),
+            urlencoding::encode(status),
+            page
+        )
+    };
+    let mut pagination_html = String::new();
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="{}">&lt; Previous</a> "#,
+            encode_minimal(&page_link(page - 1))
+        )
+        .unwrap();
+    }
+    write!(pagination_html, "Page {} of {}", page, n_pages).unwrap();
+    if page < n_pages {
+        write!(
+            pagination_html,
+            r#" <a href="{}">Next &gt;</a>"#,
+            encode_minimal(&page_link(page + 1))
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"
+<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>Subscribers</title>
+</head>
+<body>
+{msg_html}
+<form action="/admin/subscribers" method="get">
+<input type="search" name="search" placeholder="Email or name" value="{search}">
+<select name="status">{status_options}</select>
+<button type="submit">Search</button>
+</form>
+<p>{n_subscribers} subscribers found.</p>
+<table>
+<tr>
+<th>Email</th>
+<th>Name</th>
+<th>Status</th>
+<th>Subscribed at</th>
+</tr>
+{rows_html}
+</table>
+<p>{pagination_html}</p>
+<p><a href="/admin/subscribers/import">Import subscribers from a CSV file</a></p>
+<p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>
+"#,
+            search = encode_attribute(search),
+        )))
+}
+
+/// Returns one page of subscribers, most recent first, and how many match overall.
+#[tracing::instrument(skip(pool))]
+async fn get_subscribers(
+    pool: &PgPool,
+    search: &str,
+    status: &str,
+    page: i64,
+) -> Result<(Vec<SubscriberRow>, i64), anyhow::Error> {
+    // `search` is matched literally.
+    let pattern = format!(
+        "%{}%",
+        search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let status = (!status.is_empty()).then_some(status);
+    let subscribers = sqlx::query_as!(
+        SubscriberRow,
+        r#"
+SELECT id, email, name, status, subscribed_at
+FROM subscriptions
+WHERE
+(email ILIKE $1 OR name ILIKE $1) AND
+($2::text IS NULL OR status = $2)
+ORDER BY subscribed_at DESC, id
+LIMIT $3 OFFSET $4
+"#,
+        pattern,
+        status,
+        PAGE_SIZE,
+        (page - 1).saturating_mul(PAGE_SIZE),
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscribers")?;
+    let n_subscribers = sqlx::query!(
+        r#"
+SELECT COUNT(*) AS "n!"
+FROM subscriptions
+WHERE
+(email ILIKE $1 OR name ILIKE $1) AND
+($2::text IS NULL OR status = $2)
+"#,
+        pattern,
+        status,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count subscribers")?
+    .n;
+
+    Ok((subscribers, n_subscribers))
+}
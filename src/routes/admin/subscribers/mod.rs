@@ -0,0 +1,11 @@
+mod actions;
+mod details;
+mod import;
+mod list;
+
+pub use actions::{
+    confirm_subscriber_manually, delete_subscriber, unsubscribe_subscriber_manually,
+};
+pub use details::subscriber_details;
+pub use import::{import_subscribers_form, upload_subscribers};
+pub use list::list_subscribers;
@@ -1,25 +1,29 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::configuration::SubscriptionSettings;
+use crate::domain::{Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_outbox::enqueue_email;
+use crate::email_templates::TemplateKind;
+use crate::i18n::requested_locale;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use reqwest::StatusCode;
+use sha3::{Digest, Sha3_256};
 use sqlx::{PgPool, Postgres, Transaction};
-use std::convert::{TryFrom, TryInto};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize, Debug)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    /// Takes precedence over `Accept-Language`.
+    pub locale: Option<String>,
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form,pool, email_client, base_url),
+    skip(form, request, pool, subscriptions),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name,
@@ -27,103 +31,110 @@ pub struct FormData {
 )]
 pub async fn subscribe(
     form: web::Form<FormData>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
-    base_url: web::Data<ApplicationBaseUrl>,
+    subscriptions: web::Data<SubscriptionSettings>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let locale = form
+        .locale
+        .as_deref()
+        .and_then(Locale::parse)
+        .or_else(|| requested_locale(&request))
+        .unwrap_or(subscriptions.default_locale);
+    let new_subscriber = form
+        .0
+        .into_new_subscriber(locale)
+        .map_err(SubscribeError::ValidationError)?;
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connectoin from the pool.")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let subscriber = upsert_subscriber(&mut transaction, &new_subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
-    let subscriber_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscriber_token)
+    let subscriber_id = subscriber.id;
+    // Confirmed subscribers are left alone, everybody else (re)starts double opt-in.
+    // Both cases get the same response, so that the form does not reveal who is
+    // on the list: only the owner of the address learns that from their inbox.
+    let kind = if subscriber.status == "confirmed" {
+        // They get the notice in the language they signed up with, whoever filled in
+        // the form.
+        TemplateKind::AlreadySubscribed
+    } else {
+        TemplateKind::Confirmation
+    };
+    enqueue_email(&mut transaction, subscriber_id, kind)
         .await
-        .context("Failed to store the confirmation token for a subscriber")?;
+        .context("Failed to queue an email for a new subscriber.")?;
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &base_url.0,
-        &subscriber_token,
-    )
-    .await
-    .context("Failed to send a confirmation email.")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form.name)?;
-        let email = SubscriberEmail::parse(form.email)?;
-        Ok(NewSubscriber { email, name })
+impl FormData {
+    fn into_new_subscriber(self, locale: Locale) -> Result<NewSubscriber, String> {
+        let name = SubscriberName::parse(self.name)?;
+        let email = SubscriberEmail::parse(self.email)?;
+        Ok(NewSubscriber {
+            email,
+            name,
+            locale,
+        })
     }
 }
 
+pub struct StoredSubscriber {
+    pub id: Uuid,
+    pub status: String,
+    pub locale: String,
+}
+
+/// Stores a new subscriber as pending confirmation. Subscribers that are already on
+/// the list, whatever the case of their address, keep their id and address, and go
+/// back to pending confirmation unless confirmed.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
     skip(new_subscriber, transaction)
 )]
-pub async fn insert_subscriber(
+pub async fn upsert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+) -> Result<StoredSubscriber, sqlx::Error> {
+    sqlx::query_as!(
+        StoredSubscriber,
         r#"
-INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-VALUES ($1, $2, $3, $4, $5)
+INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+ON CONFLICT ((lower(email))) DO UPDATE
+SET
+name = CASE
+WHEN subscriptions.status = 'confirmed' THEN subscriptions.name
+ELSE EXCLUDED.name
+END,
+locale = CASE
+WHEN subscriptions.status = 'confirmed' THEN subscriptions.locale
+ELSE EXCLUDED.locale
+END,
+status = CASE
+WHEN subscriptions.status = 'confirmed' THEN 'confirmed'
+ELSE 'pending_confirmation'
+END
+RETURNING id, status, locale
 "#,
-        subscriber_id,
+        Uuid::new_v4(),
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now(),
-        "pending_confirmation"
+        new_subscriber.locale.as_str(),
     )
-    .execute(transaction)
-    .await?;
-
-    Ok(subscriber_id)
-}
-
-#[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
-)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
-    base_url: &str,
-    subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token,
-    );
-
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-
-    let html_body = format!("Welcome to our newsletter!<br />Click <a href=\"{}\">here</a> to confirm your subscription.", confirmation_link);
-
-    email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await?;
-
-    Ok(())
+    .fetch_one(transaction)
+    .await
 }
 
+/// Replaces the tokens of earlier requests: only the latest link works.
 #[tracing::instrument(
     name = "Store subscription token in database",
     skip(subscription_token, transaction)
@@ -134,9 +145,16 @@ pub async fn store_token(
     subscription_token: &str,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-VALUES ($1, $2)"#,
-        subscription_token,
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(StoreTokenError)?;
+    sqlx::query!(
+        r#"INSERT INTO subscription_tokens (subscription_token_hash, subscriber_id, created_at)
+VALUES ($1, $2, now())"#,
+        hash_subscription_token(subscription_token),
         subscriber_id,
     )
     .execute(transaction)
@@ -146,7 +164,13 @@ VALUES ($1, $2)"#,
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
+/// Tokens are stored hashed. They are random and long enough that a fast,
+/// unsalted hash does the job.
+pub fn hash_subscription_token(subscription_token: &str) -> String {
+    format!("{:x}", Sha3_256::digest(subscription_token.as_bytes()))
+}
+
+pub(crate) fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
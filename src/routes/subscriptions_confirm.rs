@@ -1,8 +1,16 @@
-use crate::routes::subscriptions::error_chain_fmt;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::configuration::SubscriptionSettings;
+use crate::domain::Locale;
+use crate::i18n::{messages, requested_locale};
+use crate::routes::subscriptions::{error_chain_fmt, hash_subscription_token};
+use crate::utils::see_other;
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
+use htmlescape::encode_attribute;
 use reqwest::StatusCode;
 use sqlx::PgPool;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -14,6 +22,10 @@ pub struct Parameters {
 pub enum ConfirmationError {
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error(
+        "The confirmation link has expired. Subscribe again to receive a new confirmation email."
+    )]
+    ExpiredToken,
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -28,53 +40,202 @@ impl ResponseError for ConfirmationError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
             ConfirmationError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmationError::ExpiredToken => StatusCode::GONE,
             ConfirmationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(
+/// Only shows a button: mail scanners prefetch links, but they don't submit forms.
+#[tracing::instrument(
+    name = "Show the subscription confirmation form",
+    skip(parameters, request, pool, subscriptions)
+)]
+pub async fn confirm_form(
     parameters: web::Query<Parameters>,
+    request: HttpRequest,
     pool: web::Data<PgPool>,
-) -> Result<HttpResponse, ConfirmationError> {
-    let subscriber_id = get_subscriber_id_from_token(&pool, &parameters.subscription_token)
+    subscriptions: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<ConfirmationError>> {
+    let token = get_valid_token(&pool, &parameters.subscription_token, &subscriptions)
+        .await
+        .map_err(|e| error_page(e, &request, &subscriptions))?;
+    let messages = messages(token.locale);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(confirmation_page(
+            token.locale,
+            &format!(
+                r#"<p>{}</p>
+<form action="/subscriptions/confirm" method="post">
+<input type="hidden" name="subscription_token" value="{}">
+<button type="submit">{}</button>
+</form>"#,
+                messages.confirmation_prompt,
+                encode_attribute(&parameters.subscription_token),
+                messages.confirmation_button,
+            ),
+        )))
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(form, request, pool, subscriptions)
+)]
+pub async fn confirm(
+    form: web::Form<Parameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    subscriptions: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<ConfirmationError>> {
+    let token = get_valid_token(&pool, &form.subscription_token, &subscriptions)
+        .await
+        .map_err(|e| error_page(e, &request, &subscriptions))?;
+    let confirmed = confirm_subscriber(&pool, &form.subscription_token)
+        .await
+        .context("Failed to confirm subscriber.")
+        .map_err(|e| error_page(e.into(), &request, &subscriptions))?;
+    // Someone else used the link first, or the subscriber is no longer pending.
+    if !confirmed {
+        return Err(error_page(
+            ConfirmationError::UnknownToken,
+            &request,
+            &subscriptions,
+        ));
+    }
+    match &subscriptions.confirmation_redirect_url {
+        Some(url) => Ok(see_other(url)),
+        None => Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(confirmation_page(
+                token.locale,
+                &format!("<p>{}</p>", messages(token.locale).confirmed),
+            ))),
+    }
+}
+
+async fn get_valid_token(
+    pool: &PgPool,
+    subscription_token: &str,
+    subscriptions: &SubscriptionSettings,
+) -> Result<StoredToken, ConfirmationError> {
+    let token = get_token(pool, subscription_token, subscriptions.default_locale)
         .await
         .context("Failed to get subscriber token.")?
         .ok_or(ConfirmationError::UnknownToken)?;
-    confirm_subscriber(&pool, subscriber_id)
-        .await
-        .context("Failed to confirm subscriber.")?;
-    Ok(HttpResponse::Ok().finish())
+    if token.age >= subscriptions.confirmation_token_ttl() {
+        return Err(ConfirmationError::ExpiredToken);
+    }
+    Ok(token)
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-pub async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
-        subscriber_id
+/// In the language of the browser: we may not know whose link this is.
+fn error_page(
+    e: ConfirmationError,
+    request: &HttpRequest,
+    subscriptions: &SubscriptionSettings,
+) -> InternalError<ConfirmationError> {
+    let locale = requested_locale(request).unwrap_or(subscriptions.default_locale);
+    let messages = messages(locale);
+    let message = match e {
+        ConfirmationError::UnknownToken => messages.unknown_confirmation_token,
+        ConfirmationError::ExpiredToken => messages.expired_confirmation_token,
+        ConfirmationError::UnexpectedError(_) => messages.unexpected_error,
+    };
+    let response = HttpResponse::build(e.status_code())
+        .content_type(ContentType::html())
+        .body(confirmation_page(locale, &format!("<p>{}</p>", message)));
+    InternalError::from_response(e, response)
+}
+
+fn confirmation_page(locale: Locale, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{}</title>
+</head>
+<body>
+{}
+</body>
+</html>"#,
+        locale.as_str(),
+        messages(locale).confirmation_title,
+        body
     )
-    .execute(pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+}
+
+/// Consumes the token. Returns whether a pending subscriber was confirmed: a link
+/// never brings back a subscriber who has left in the meantime.
+#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscription_token, pool))]
+pub async fn confirm_subscriber(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = sqlx::query!(
+        r#"
+DELETE FROM subscription_tokens
+WHERE subscription_token_hash = $1
+RETURNING subscriber_id
+"#,
+        hash_subscription_token(subscription_token),
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .map(|r| r.subscriber_id);
+    let confirmed = match subscriber_id {
+        Some(subscriber_id) => {
+            sqlx::query!(
+                r#"
+UPDATE subscriptions SET status = 'confirmed'
+WHERE id = $1 AND status = 'pending_confirmation'
+"#,
+                subscriber_id
+            )
+            .execute(&mut transaction)
+            .await?
+            .rows_affected()
+                > 0
+        }
+        None => false,
+    };
+    transaction.commit().await?;
+
+    Ok(confirmed)
+}
 
-    Ok(())
+pub struct StoredToken {
+    pub subscriber_id: Uuid,
+    pub age: Duration,
+    pub locale: Locale,
 }
 
 #[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-pub async fn get_subscriber_id_from_token(
+pub async fn get_token(
     pool: &PgPool,
     subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+    default_locale: Locale,
+) -> Result<Option<StoredToken>, sqlx::Error> {
     let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
-        subscription_token,
+        r#"
+SELECT
+t.subscriber_id,
+EXTRACT(EPOCH FROM now() - t.created_at)::float8 AS "age_seconds!",
+s.locale
+FROM subscription_tokens t
+JOIN subscriptions s ON s.id = t.subscriber_id
+WHERE t.subscription_token_hash = $1
+"#,
+        hash_subscription_token(subscription_token),
     )
     .fetch_optional(pool)
     .await?;
 
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result.map(|r| StoredToken {
+        subscriber_id: r.subscriber_id,
+        age: Duration::from_secs_f64(r.age_seconds.max(0.0)),
+        locale: Locale::parse(&r.locale).unwrap_or(default_locale),
+    }))
 }
@@ -0,0 +1,181 @@
+use crate::configuration::SubscriptionSettings;
+use crate::domain::Locale;
+use crate::i18n::{messages, requested_locale};
+use crate::routes::subscriptions::error_chain_fmt;
+use crate::unsubscribe::UnsubscribeLinks;
+use actix_web::error::InternalError;
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use reqwest::StatusCode;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscriber_id: Uuid,
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe link is not valid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            UnsubscribeError::InvalidToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Asks for confirmation: link scanners follow every link they find in an email.
+#[tracing::instrument(
+    name = "Show the unsubscribe form",
+    skip(parameters, request, pool, links, subscriptions)
+)]
+pub async fn unsubscribe_form(
+    parameters: web::Query<UnsubscribeParameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    links: web::Data<UnsubscribeLinks>,
+    subscriptions: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<UnsubscribeError>> {
+    if !links.verify(parameters.subscriber_id, &parameters.token) {
+        return Err(error_page(
+            UnsubscribeError::InvalidToken,
+            &request,
+            &subscriptions,
+        ));
+    }
+    let locale = get_subscriber_locale(&pool, parameters.subscriber_id)
+        .await
+        .context("Failed to get the locale of the subscriber.")
+        .map_err(|e| error_page(e.into(), &request, &subscriptions))?
+        .unwrap_or(subscriptions.default_locale);
+    let messages = messages(locale);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(unsubscribe_page(
+            locale,
+            &format!(
+                r#"<p>{}</p>
+<form action="{}" method="post">
+<button type="submit">{}</button>
+</form>"#,
+                messages.unsubscribe_prompt,
+                htmlescape::encode_attribute(&links.url(parameters.subscriber_id)),
+                messages.unsubscribe_button,
+            ),
+        )))
+}
+
+/// Also serves RFC 8058 one-click requests, which carry the parameters in the
+/// query string and `List-Unsubscribe=One-Click` in the body.
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, request, pool, links, subscriptions)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    links: web::Data<UnsubscribeLinks>,
+    subscriptions: web::Data<SubscriptionSettings>,
+) -> Result<HttpResponse, InternalError<UnsubscribeError>> {
+    if !links.verify(parameters.subscriber_id, &parameters.token) {
+        return Err(error_page(
+            UnsubscribeError::InvalidToken,
+            &request,
+            &subscriptions,
+        ));
+    }
+    let locale = unsubscribe_subscriber(&pool, parameters.subscriber_id)
+        .await
+        .context("Failed to unsubscribe the subscriber.")
+        .map_err(|e| error_page(e.into(), &request, &subscriptions))?
+        .unwrap_or(subscriptions.default_locale);
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(unsubscribe_page(
+            locale,
+            &format!("<p>{}</p>", messages(locale).unsubscribed),
+        )))
+}
+
+fn error_page(
+    e: UnsubscribeError,
+    request: &HttpRequest,
+    subscriptions: &SubscriptionSettings,
+) -> InternalError<UnsubscribeError> {
+    let locale = requested_locale(request).unwrap_or(subscriptions.default_locale);
+    let messages = messages(locale);
+    let message = match e {
+        UnsubscribeError::InvalidToken => messages.invalid_unsubscribe_link,
+        UnsubscribeError::UnexpectedError(_) => messages.unexpected_error,
+    };
+    let response = HttpResponse::build(e.status_code())
+        .content_type(ContentType::html())
+        .body(unsubscribe_page(locale, &format!("<p>{}</p>", message)));
+    InternalError::from_response(e, response)
+}
+
+fn unsubscribe_page(locale: Locale, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{}">
+<head>
+<meta http-equiv="content-type" content="text/html; charset=utf-8">
+<title>{}</title>
+</head>
+<body>
+{}
+</body>
+</html>"#,
+        locale.as_str(),
+        messages(locale).unsubscribe_title,
+        body
+    )
+}
+
+#[tracing::instrument(name = "Get the locale of a subscriber", skip(pool))]
+async fn get_subscriber_locale(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Locale>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT locale FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|r| Locale::parse(&r.locale)))
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(pool))]
+/// Returns the locale of the subscriber, if they still exist.
+async fn unsubscribe_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Locale>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"UPDATE subscriptions SET status = 'unsubscribed' WHERE id = $1 RETURNING locale"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.and_then(|r| Locale::parse(&r.locale)))
+}
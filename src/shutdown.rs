@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Fires the shutdown signal for every [`Shutdown`] created from the same channel.
+#[derive(Clone)]
+pub struct ShutdownTrigger(Arc<watch::Sender<bool>>);
+
+/// Resolves once a graceful shutdown has been requested.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (sender, receiver) = watch::channel(false);
+    (ShutdownTrigger(Arc::new(sender)), Shutdown(receiver))
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        // Nobody is left to notify if every receiver is gone, which is fine.
+        let _ = self.0.send(true);
+    }
+
+    /// Triggers the shutdown on SIGTERM or SIGINT.
+    pub async fn trigger_on_signal(self) {
+        wait_for_signal().await;
+        tracing::info!("Received a termination signal, shutting down gracefully.");
+        self.trigger();
+    }
+}
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn triggered(&mut self) {
+        while !self.is_triggered() {
+            if self.0.changed().await.is_err() {
+                // The trigger is gone: the shutdown can no longer be requested.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen for Ctrl+C");
+}
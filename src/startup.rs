@@ -1,10 +1,15 @@
 use crate::authentication::reject_anonymous_users;
-use crate::configuration::DatabaseSettings;
-use crate::email_client::EmailClient;
+use crate::configuration::{ApplicationSettings, DatabaseSettings, SubscriptionSettings};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, log_out, publish_newsletter,
-    publish_newsletter_form,
+    admin_dashboard, cancel_newsletter, change_password, change_password_form,
+    confirm_subscriber_manually, delete_subscriber, edit_newsletter, edit_newsletter_form,
+    failed_deliveries, import_subscribers_form, list_subscribers, log_out, newsletter_progress,
+    pause_newsletter, publish_newsletter, publish_newsletter_form, requeue_failed_deliveries,
+    resume_newsletter, scheduled_newsletters, sending_newsletters, subscriber_details,
+    unsubscribe_subscriber_manually, upload_subscribers,
 };
+use crate::shutdown::Shutdown;
+use crate::unsubscribe::UnsubscribeLinks;
 use crate::{configuration::Settings, routes};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
@@ -27,7 +32,6 @@ pub struct Application {
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
-        let email_client = configuration.email_client.client();
         let listener = TcpListener::bind(format!(
             "{}:{}",
             configuration.application.host, configuration.application.port
@@ -37,9 +41,8 @@ impl Application {
         let server = run(
             listener,
             connection_pool,
-            email_client,
-            configuration.application.base_url,
-            configuration.application.hmac_secret,
+            configuration.application,
+            configuration.subscriptions,
             configuration.redis_uri,
         )
         .await?;
@@ -51,7 +54,14 @@ impl Application {
         self.port
     }
 
-    pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
+    /// Runs until `shutdown` fires, then stops accepting connections and gives
+    /// in-flight requests up to the configured shutdown timeout to complete.
+    pub async fn run_until_stopped(self, mut shutdown: Shutdown) -> Result<(), std::io::Error> {
+        let handle = self.server.handle();
+        tokio::spawn(async move {
+            shutdown.triggered().await;
+            handle.stop(true).await;
+        });
         self.server.await
     }
 }
@@ -67,13 +77,20 @@ pub struct ApplicationBaseUrl(pub String);
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
-    hmac_secret: Secret<String>,
+    application: ApplicationSettings,
+    subscriptions: SubscriptionSettings,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
+    let ApplicationSettings {
+        base_url,
+        hmac_secret,
+        shutdown_timeout_seconds,
+        ..
+    } = application;
+    let subscriptions = web::Data::new(subscriptions);
     let connection = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
+    let unsubscribe_links =
+        web::Data::new(UnsubscribeLinks::new(base_url.clone(), hmac_secret.clone()));
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
@@ -96,19 +113,92 @@ async fn run(
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/newsletters", web::get().to(publish_newsletter_form)),
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route(
+                        "/newsletters/scheduled",
+                        web::get().to(scheduled_newsletters),
+                    )
+                    .route("/newsletters/sending", web::get().to(sending_newsletters))
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::get().to(newsletter_progress),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/edit",
+                        web::get().to(edit_newsletter_form),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/edit",
+                        web::post().to(edit_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/pause",
+                        web::post().to(pause_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/resume",
+                        web::post().to(resume_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/cancel",
+                        web::post().to(cancel_newsletter),
+                    )
+                    .route("/deliveries/failures", web::get().to(failed_deliveries))
+                    .route(
+                        "/deliveries/failures/requeue",
+                        web::post().to(requeue_failed_deliveries),
+                    )
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    // Before `{subscriber_id}`, which would otherwise match it.
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(upload_subscribers))
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/unsubscribe",
+                        web::post().to(unsubscribe_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    ),
             )
             .route("/login", web::get().to(routes::login_form))
             .route("/login", web::post().to(routes::login))
             .route("/health_check", web::get().to(routes::health_check))
             .route("/subscriptions", web::post().to(routes::subscribe))
-            .route("/subscriptions/confirm", web::get().to(routes::confirm))
+            .route(
+                "/subscriptions/confirm",
+                web::get().to(routes::confirm_form),
+            )
+            .route("/subscriptions/confirm", web::post().to(routes::confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                web::get().to(routes::unsubscribe_form),
+            )
+            .route(
+                "/subscriptions/unsubscribe",
+                web::post().to(routes::unsubscribe),
+            )
             .app_data(connection.clone())
-            .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(unsubscribe_links.clone())
+            .app_data(subscriptions.clone())
             .app_data(web::Data::new(HmacSecret(hmac_secret.clone())))
     })
     .listen(listener)?
+    // Signals are handled by the caller, see `Application::run_until_stopped`.
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout_seconds)
     .run();
 
     Ok(server)
@@ -0,0 +1,317 @@
+use crate::domain::{Locale, NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_outbox::enqueue_email;
+use crate::email_templates::TemplateKind;
+use crate::routes::error_chain_fmt;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::hash_map::{Entry, HashMap};
+use uuid::Uuid;
+
+/// How imported subscribers join the list.
+#[derive(Debug, Clone)]
+pub enum ImportMode {
+    /// They get a confirmation email, as if they had used the subscription form.
+    Pending,
+    /// They agreed to receive the newsletter elsewhere, e.g. on the tool we migrate from.
+    Confirmed { consent_source: String },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RowOutcome {
+    Accepted,
+    Rejected(String),
+}
+
+#[derive(Debug)]
+pub struct ImportedRow {
+    /// Line of the row in the CSV file, the header being line 1.
+    pub line: u64,
+    pub email: String,
+    pub outcome: RowOutcome,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub rows: Vec<ImportedRow>,
+}
+
+impl ImportReport {
+    pub fn n_accepted(&self) -> usize {
+        self.rows
+            .iter()
+            .filter(|r| r.outcome == RowOutcome::Accepted)
+            .count()
+    }
+
+    pub fn n_rejected(&self) -> usize {
+        self.rows.len() - self.n_accepted()
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    /// The file as a whole can't be imported, e.g. it lacks an `email` column.
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Imports the subscribers of a CSV file with `email` and `name` columns, and an
+/// optional `locale` one.
+///
+/// Rows are rejected one by one: invalid details, duplicates within the file and
+/// addresses already on the list, whatever their status, do not stop the import.
+/// Accepted rows are stored in a single transaction.
+#[tracing::instrument(
+    skip(pool, csv),
+    fields(n_accepted = tracing::field::Empty, n_rejected = tracing::field::Empty)
+)]
+pub async fn import_subscribers(
+    pool: &PgPool,
+    default_locale: Locale,
+    mode: &ImportMode,
+    csv: &[u8],
+) -> Result<ImportReport, ImportError> {
+    let rows = parse_rows(csv, default_locale)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let mut report = ImportReport::default();
+    for (line, row) in rows {
+        let (email, outcome) = match row {
+            Ok(new_subscriber) => {
+                let inserted = insert_subscriber(&mut transaction, mode, &new_subscriber).await?;
+                let outcome = if inserted {
+                    RowOutcome::Accepted
+                } else {
+                    RowOutcome::Rejected("Already on the list.".into())
+                };
+                (new_subscriber.email.as_ref().to_owned(), outcome)
+            }
+            Err((email, reason)) => (email, RowOutcome::Rejected(reason)),
+        };
+        report.rows.push(ImportedRow {
+            line,
+            email,
+            outcome,
+        });
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to import subscribers")?;
+
+    let span = tracing::Span::current();
+    span.record("n_accepted", report.n_accepted());
+    span.record("n_rejected", report.n_rejected());
+    Ok(report)
+}
+
+/// A valid subscriber, or the email of the row and why it was rejected.
+type ParsedRow = Result<NewSubscriber, (String, String)>;
+
+fn parse_rows(csv: &[u8], default_locale: Locale) -> Result<Vec<(u64, ParsedRow)>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(csv);
+    let headers = reader
+        .headers()
+        .map_err(|e| ImportError::InvalidFile(format!("Failed to read the header: {}", e)))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let email_column = column("email")
+        .ok_or_else(|| ImportError::InvalidFile("The file has no `email` column.".into()))?;
+    let name_column = column("name")
+        .ok_or_else(|| ImportError::InvalidFile("The file has no `name` column.".into()))?;
+    let locale_column = column("locale");
+
+    // Addresses are compared ignoring case, the domain part is case-insensitive and
+    // few mail servers treat the local part otherwise.
+    let mut first_lines = HashMap::new();
+    // `csv` skips blank lines without counting them, and starts the next record
+    // at the first of them: lines are counted here instead.
+    let (mut counted_until, mut n_lines) = (0, 1);
+    let mut line_at = |position: Option<&csv::Position>| {
+        let mut byte = position.map_or(counted_until, |p| p.byte() as usize);
+        while matches!(csv.get(byte), Some(b'\n' | b'\r')) {
+            byte += 1;
+        }
+        n_lines += count_newlines(&csv[counted_until..byte]);
+        counted_until = byte;
+        n_lines
+    };
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = line_at(e.position());
+                rows.push((line, Err((String::new(), format!("Unreadable row: {}", e)))));
+                continue;
+            }
+        };
+        let line = line_at(record.position());
+        let email = record.get(email_column).unwrap_or_default().to_owned();
+        let row = parse_row(
+            email.clone(),
+            record.get(name_column).unwrap_or_default(),
+            locale_column.and_then(|c| record.get(c)),
+            default_locale,
+        )
+        .map_err(|reason| (email.clone(), reason))
+        .and_then(
+            |new_subscriber| match first_lines.entry(email.to_lowercase()) {
+                Entry::Occupied(first_line) => {
+                    Err((email, format!("Duplicate of line {}.", first_line.get())))
+                }
+                Entry::Vacant(first_line) => {
+                    first_line.insert(line);
+                    Ok(new_subscriber)
+                }
+            },
+        );
+        rows.push((line, row));
+    }
+    Ok(rows)
+}
+
+fn count_newlines(bytes: &[u8]) -> u64 {
+    bytes.iter().filter(|b| **b == b'\n').count() as u64
+}
+
+fn parse_row(
+    email: String,
+    name: &str,
+    locale: Option<&str>,
+    default_locale: Locale,
+) -> Result<NewSubscriber, String> {
+    let email = SubscriberEmail::parse(email)?;
+    let name = SubscriberName::parse(name.to_owned())?;
+    let locale = match locale {
+        None | Some("") => default_locale,
+        Some(locale) => {
+            Locale::parse(locale).ok_or_else(|| format!("{} is not a supported locale.", locale))?
+        }
+    };
+    Ok(NewSubscriber {
+        email,
+        name,
+        locale,
+    })
+}
+
+/// Returns `false` if the address is already on the list.
+async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    mode: &ImportMode,
+    new_subscriber: &NewSubscriber,
+) -> Result<bool, anyhow::Error> {
+    let subscriber_id = Uuid::new_v4();
+    let (status, consent_source) = match mode {
+        ImportMode::Pending => ("pending_confirmation", None),
+        ImportMode::Confirmed { consent_source } => ("confirmed", Some(consent_source)),
+    };
+    let inserted = sqlx::query!(
+        r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale, consent_source)
+VALUES ($1, $2, $3, now(), $4, $5, $6)
+ON CONFLICT ((lower(email))) DO NOTHING
+"#,
+        subscriber_id,
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        status,
+        new_subscriber.locale.as_str(),
+        consent_source,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to insert an imported subscriber")?
+    .rows_affected()
+        > 0;
+
+    if inserted && matches!(mode, ImportMode::Pending) {
+        enqueue_email(transaction, subscriber_id, TemplateKind::Confirmation)
+            .await
+            .context("Failed to queue a confirmation email for an imported subscriber")?;
+    }
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(csv: &str) -> Vec<(u64, ParsedRow)> {
+        parse_rows(csv.as_bytes(), Locale::En).unwrap()
+    }
+
+    fn rejection(row: &ParsedRow) -> &str {
+        &row.as_ref().err().unwrap().1
+    }
+
+    #[test]
+    fn valid_rows_are_parsed_with_their_line() {
+        let rows = parse("email,name\nursula@example.com,Ursula\n\nada@example.com,Ada\n");
+
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, 2);
+        assert_eq!(rows[1].0, 4);
+        let subscriber = rows[1].1.as_ref().unwrap();
+        assert_eq!(subscriber.email.as_ref(), "ada@example.com");
+        assert_eq!(subscriber.name.as_ref(), "Ada");
+        assert_eq!(subscriber.locale, Locale::En);
+    }
+
+    #[test]
+    fn columns_can_be_in_any_order_and_the_locale_is_optional() {
+        let rows = parse("Name, Locale, Email\nUrsula,de,ursula@example.com\nAda,,ada@example.com");
+
+        assert_eq!(rows[0].1.as_ref().unwrap().locale, Locale::De);
+        assert_eq!(rows[1].1.as_ref().unwrap().locale, Locale::En);
+    }
+
+    #[test]
+    fn a_file_without_an_email_column_is_rejected() {
+        assert!(matches!(
+            parse_rows(b"address,name\nursula@example.com,Ursula", Locale::En),
+            Err(ImportError::InvalidFile(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_rows_are_rejected_with_a_reason() {
+        let rows = parse(
+            "email,name,locale\n\
+             not-an-email,Ursula,en\n\
+             ada@example.com,,en\n\
+             grace@example.com,Grace,fr\n\
+             marie@example.com\n",
+        );
+
+        assert_eq!(
+            rejection(&rows[0].1),
+            "not-an-email is not a valid subscriber email"
+        );
+        assert!(rows[1].1.is_err());
+        assert_eq!(rejection(&rows[2].1), "fr is not a supported locale.");
+        assert!(rejection(&rows[3].1).starts_with("Unreadable row"));
+        assert_eq!(rows[3].0, 5);
+    }
+
+    #[test]
+    fn duplicates_within_the_file_are_rejected() {
+        let rows = parse("email,name\nursula@example.com,Ursula\nURSULA@example.com,Ursula K.");
+
+        assert!(rows[0].1.is_ok());
+        assert_eq!(rejection(&rows[1].1), "Duplicate of line 2.");
+    }
+}
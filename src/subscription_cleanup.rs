@@ -0,0 +1,76 @@
+use crate::configuration::SubscriptionSettings;
+use crate::shutdown::Shutdown;
+use sqlx::PgPool;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub n_expired_tokens: u64,
+    pub n_purged_subscribers: u64,
+}
+
+/// Deletes confirmation tokens once they have been expired for the retention window
+/// and, if enabled, the subscribers that were left without a way to confirm.
+#[tracing::instrument(skip_all, fields(report = tracing::field::Empty), err)]
+pub async fn try_cleanup_subscriptions(
+    pool: &PgPool,
+    settings: &SubscriptionSettings,
+) -> Result<CleanupReport, anyhow::Error> {
+    let ttl = settings.confirmation_token_ttl().as_secs_f64();
+    let retention = settings.expired_token_retention().as_secs_f64();
+    let mut transaction = pool.begin().await?;
+    let n_expired_tokens = sqlx::query!(
+        r#"
+DELETE FROM subscription_tokens
+WHERE created_at <= now() - make_interval(secs => $1)
+"#,
+        ttl + retention
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+
+    let n_purged_subscribers = if settings.purge_unconfirmed_subscribers {
+        sqlx::query!(
+            r#"
+DELETE FROM subscriptions s
+WHERE
+s.status = 'pending_confirmation' AND
+s.subscribed_at <= now() - make_interval(secs => $1) AND
+NOT EXISTS (SELECT 1 FROM subscription_tokens t WHERE t.subscriber_id = s.id) AND
+NOT EXISTS (SELECT 1 FROM email_outbox o WHERE o.subscriber_id = s.id) AND
+NOT EXISTS (SELECT 1 FROM email_outbox_failures f WHERE f.subscriber_id = s.id) AND
+NOT EXISTS (SELECT 1 FROM issue_deliveries d WHERE d.subscriber_id = s.id)
+"#,
+            ttl
+        )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected()
+    } else {
+        0
+    };
+    transaction.commit().await?;
+
+    let report = CleanupReport {
+        n_expired_tokens,
+        n_purged_subscribers,
+    };
+    tracing::Span::current().record("report", tracing::field::debug(&report));
+    Ok(report)
+}
+
+pub async fn cleanup_loop(
+    pool: PgPool,
+    settings: SubscriptionSettings,
+    mut shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        // Errors have been logged already, we'll try again on the next tick.
+        let _ = try_cleanup_subscriptions(&pool, &settings).await;
+        tokio::select! {
+            _ = tokio::time::sleep(settings.cleanup_interval()) => {}
+            _ = shutdown.triggered() => {}
+        }
+    }
+    Ok(())
+}
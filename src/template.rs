@@ -0,0 +1,73 @@
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("A `{{{{` is never closed")]
+    Unclosed,
+    #[error("`{0}` is not a known variable")]
+    UnknownVariable(String),
+    #[error("`{0}` is required but never used")]
+    MissingVariable(String),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Segment {
+    Text(String),
+    Variable(String),
+}
+
+/// A string with `{{variable}}` placeholders.
+#[derive(Debug)]
+pub struct Template(Vec<Segment>);
+
+impl Template {
+    /// Rejects placeholders that are not in `variables`.
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let end = rest[start..].find("}}").ok_or(TemplateError::Unclosed)? + start;
+            let variable = rest[start + 2..end].trim();
+            if !variables.contains(&variable) {
+                return Err(TemplateError::UnknownVariable(variable.to_string()));
+            }
+            segments.push(Segment::Variable(variable.to_string()));
+            rest = &rest[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Self(segments))
+    }
+
+    /// Renders `source` as is, placeholders included.
+    pub fn literal(source: &str) -> Self {
+        Self(vec![Segment::Text(source.to_string())])
+    }
+
+    pub fn uses(&self, variable: &str) -> bool {
+        self.0
+            .iter()
+            .any(|s| matches!(s, Segment::Variable(v) if v == variable))
+    }
+
+    /// Values go through `escape`, the rest of the template doesn't.
+    pub fn render(&self, variables: &[(&str, &str)], escape: fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+        for segment in &self.0 {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Variable(variable) => {
+                    let value = variables
+                        .iter()
+                        .find(|(name, _)| name == variable)
+                        .map(|(_, value)| *value)
+                        .unwrap_or_default();
+                    rendered.push_str(&escape(value));
+                }
+            }
+        }
+        rendered
+    }
+}
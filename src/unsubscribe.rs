@@ -0,0 +1,81 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Builds and verifies the signed links subscribers use to leave the list.
+/// They never expire: a link found in an old issue must keep working.
+#[derive(Clone)]
+pub struct UnsubscribeLinks {
+    base_url: String,
+    hmac_secret: Secret<String>,
+}
+
+impl UnsubscribeLinks {
+    pub fn new(base_url: String, hmac_secret: Secret<String>) -> Self {
+        Self {
+            base_url,
+            hmac_secret,
+        }
+    }
+
+    pub fn url(&self, subscriber_id: Uuid) -> String {
+        format!(
+            "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+            self.base_url,
+            subscriber_id,
+            self.token(subscriber_id)
+        )
+    }
+
+    pub fn token(&self, subscriber_id: Uuid) -> String {
+        hex::encode(self.mac(subscriber_id).finalize().into_bytes())
+    }
+
+    /// Compares in constant time.
+    pub fn verify(&self, subscriber_id: Uuid, token: &str) -> bool {
+        match hex::decode(token) {
+            Ok(tag) => self.mac(subscriber_id).verify_slice(&tag).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn mac(&self, subscriber_id: Uuid) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes()).unwrap();
+        // Keeps these tags apart from anything else signed with the same secret.
+        mac.update(b"unsubscribe:");
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeLinks;
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn links(secret: &str) -> UnsubscribeLinks {
+        UnsubscribeLinks::new("http://127.0.0.1".into(), Secret::new(secret.into()))
+    }
+
+    #[test]
+    fn a_token_is_valid_for_its_own_subscriber_only() {
+        let links = links("secret");
+        let subscriber_id = Uuid::new_v4();
+        let token = links.token(subscriber_id);
+
+        assert!(links.verify(subscriber_id, &token));
+        assert!(!links.verify(Uuid::new_v4(), &token));
+    }
+
+    #[test]
+    fn tokens_signed_with_another_secret_are_rejected() {
+        let subscriber_id = Uuid::new_v4();
+        let token = links("another-secret").token(subscriber_id);
+
+        assert!(!links("secret").verify(subscriber_id, &token));
+        assert!(!links("secret").verify(subscriber_id, "not-hex"));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::helpers::{assert_is_redirect_to, clean_db, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+struct ImportedSubscriber {
+    status: String,
+    locale: String,
+    consent_source: Option<String>,
+}
+
+async fn imported_subscriber(app: &TestApp, email: &str) -> ImportedSubscriber {
+    sqlx::query_as!(
+        ImportedSubscriber,
+        "SELECT status, locale, consent_source FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("email,name\nursula@example.com,Ursula", "pending", None)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn pending_imports_send_a_confirmation_email() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriber_import(
+            "email,name,locale\nursula@example.com,Ursula,de\nada@example.com,Ada,",
+            "pending",
+            None,
+        )
+        .await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>2 accepted, 0 rejected.</p>"));
+    let subscriber = imported_subscriber(&app, "ursula@example.com").await;
+    assert_eq!(subscriber.status, "pending_confirmation");
+    assert_eq!(subscriber.locale, "de");
+    assert_eq!(subscriber.consent_source, None);
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = app.post_confirmation(&confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn confirmed_imports_record_the_source_of_consent() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriber_import(
+            "email,name\nursula@example.com,Ursula",
+            "confirmed",
+            Some("Double opt-in on our previous tool"),
+        )
+        .await;
+    app.dispatch_outbox_emails().await;
+
+    // Assert
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>1 accepted, 0 rejected.</p>"));
+    let subscriber = imported_subscriber(&app, "ursula@example.com").await;
+    assert_eq!(subscriber.status, "confirmed");
+    assert_eq!(
+        subscriber.consent_source.as_deref(),
+        Some("Double opt-in on our previous tool")
+    );
+}
+
+#[tokio::test]
+async fn confirmed_imports_require_the_source_of_consent() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import(
+            "email,name\nursula@example.com,Ursula",
+            "confirmed",
+            Some(" "),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app.follow_redirect(&response).await;
+    assert!(html_page.contains("gave their consent"));
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 0);
+}
+
+#[tokio::test]
+async fn the_report_lists_every_rejected_row_and_why() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscriber_import(
+        "email,name\nada@example.com,Ada",
+        "confirmed",
+        Some("Old tool"),
+    )
+    .await;
+
+    // Act
+    let response = app
+        .post_subscriber_import(
+            "email,name\n\
+             ursula@example.com,Ursula\n\
+             not-an-email,Grace\n\
+             URSULA@example.com,Ursula\n\
+             Ada@example.com,Ada\n",
+            "confirmed",
+            Some("Old tool"),
+        )
+        .await;
+
+    // Assert
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>1 accepted, 3 rejected.</p>"));
+    assert!(html_page.contains("<tr><td>2</td><td>ursula@example.com</td><td>Accepted</td></tr>"));
+    assert!(html_page.contains(
+        "<tr><td>3</td><td>not-an-email</td><td>Rejected: not-an-email is not a valid subscriber email</td></tr>"
+    ));
+    assert!(html_page.contains(
+        "<tr><td>4</td><td>URSULA@example.com</td><td>Rejected: Duplicate of line 2.</td></tr>"
+    ));
+    assert!(html_page.contains(
+        "<tr><td>5</td><td>Ada@example.com</td><td>Rejected: Already on the list.</td></tr>"
+    ));
+}
+
+#[tokio::test]
+async fn a_file_without_the_required_columns_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_import("address\nursula@example.com", "pending", None)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app.follow_redirect(&response).await;
+    assert!(html_page.contains("The file has no `email` column."));
+}
@@ -0,0 +1,294 @@
+use crate::helpers::{
+    assert_is_redirect_to, batch_accepted, clean_db, create_confirmed_subscriber,
+    create_unconfirmed_subscriber, spawn_app, TestApp,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    insert_subscriber_at(app, email, name, status, Duration::zero()).await
+}
+
+/// `age` pushes `subscribed_at` into the past, to control the order of the list.
+async fn insert_subscriber_at(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    age: Duration,
+) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+VALUES ($1, $2, $3, $4, $5)
+"#,
+        subscriber_id,
+        email,
+        name,
+        Utc::now() - age,
+        status
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_status(app: &TestApp, subscriber_id: Uuid) -> Option<String> {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_optional(&app.db_pool)
+    .await
+    .unwrap()
+    .map(|r| r.status)
+}
+
+async fn only_subscriber_id(app: &TestApp) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+
+    // Act & Assert
+    assert_is_redirect_to(&app.get_subscribers("").await, "/login");
+    assert_is_redirect_to(&app.get_subscriber_details(subscriber_id).await, "/login");
+    for action in ["confirm", "unsubscribe", "delete"] {
+        let response = app.post_subscriber_action(subscriber_id, action).await;
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_by_email_or_name_and_filtered_by_status() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    insert_subscriber(&app, "ada@example.com", "Ada Ursulina", "unsubscribed").await;
+    insert_subscriber(&app, "grace@example.com", "Grace", "confirmed").await;
+
+    // Act - Part 1 - Everyone
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p>3 subscribers found.</p>"));
+
+    // Act - Part 2 - Search, ignoring case
+    let html_page = app.get_subscribers_html("search=URSUL").await;
+    assert!(html_page.contains("<p>2 subscribers found.</p>"));
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(html_page.contains("ada@example.com"));
+    assert!(!html_page.contains("grace@example.com"));
+
+    // Act - Part 3 - Search and status
+    let html_page = app
+        .get_subscribers_html("search=ursul&status=confirmed")
+        .await;
+    assert!(html_page.contains("<p>1 subscribers found.</p>"));
+    assert!(html_page.contains("ursula@example.com"));
+
+    // Act - Part 4 - Wildcards are matched literally
+    let html_page = app.get_subscribers_html("search=%25").await;
+    assert!(html_page.contains("<p>0 subscribers found.</p>"));
+}
+
+#[tokio::test]
+async fn filtering_by_an_unknown_status_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscribers("status=vip").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn the_subscriber_list_is_paginated() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..51 {
+        insert_subscriber_at(
+            &app,
+            &format!("subscriber{}@example.com", i),
+            "Subscriber",
+            "confirmed",
+            Duration::minutes(i),
+        )
+        .await;
+    }
+
+    // Act - Part 1 - Most recent first
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p>51 subscribers found.</p>"));
+    assert!(html_page.contains("Page 1 of 2"));
+    assert!(html_page.contains("subscriber49@example.com"));
+    assert!(!html_page.contains("subscriber50@example.com"));
+    assert!(html_page.contains("/admin/subscribers?search=&amp;status=&amp;page=2"));
+
+    // Act - Part 2 - The last page
+    let html_page = app.get_subscribers_html("page=2").await;
+    assert!(html_page.contains("Page 2 of 2"));
+    assert!(html_page.contains("subscriber50@example.com"));
+    assert!(!html_page.contains("subscriber49@example.com"));
+}
+
+#[tokio::test]
+async fn the_details_page_shows_the_delivery_history() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+
+    // Assert
+    assert!(html_page.contains("<tr><th>Status</th><td>confirmed</td></tr>"));
+    assert!(html_page.contains("Newsletter title</a></td>"));
+    assert!(html_page.contains("<td>sent</td>"));
+}
+
+#[tokio::test]
+async fn the_details_page_of_an_unknown_subscriber_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.get_subscriber_details(Uuid::new_v4()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn pending_subscribers_can_be_confirmed_manually() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "confirm").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    let html_page = app.get_subscriber_details_html(subscriber_id).await;
+    assert!(html_page.contains("<p><i>The subscriber has been confirmed.</i></p>"));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await.as_deref(),
+        Some("confirmed")
+    );
+    // The confirmation link is spent.
+    let response = app.post_confirmation(&confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_unsubscribed_manually() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_action(subscriber_id, "unsubscribe")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/subscribers/{}", subscriber_id));
+    assert_eq!(
+        subscriber_status(&app, subscriber_id).await.as_deref(),
+        Some("unsubscribed")
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_deleted_along_with_their_history() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscriber_id = only_subscriber_id(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .mount(&app.email_server)
+        .await;
+    app.post_publish_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    assert!(html_page.contains("<p>0 subscribers found.</p>"));
+    assert_eq!(subscriber_status(&app, subscriber_id).await, None);
+}
+
+#[tokio::test]
+async fn acting_on_an_unknown_subscriber_redirects_to_the_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscriber_action(Uuid::new_v4(), "unsubscribe")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("<p><i>The subscriber no longer exists.</i></p>"));
+}
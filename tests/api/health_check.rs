@@ -9,7 +9,7 @@ async fn health_check_works() {
     dbg!(format!("{}/health_check", &app.address));
 
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failed to execute request.");
@@ -1,13 +1,23 @@
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use fake::faker::internet::en::SafeEmail;
+use fake::faker::name::en::Name;
+use fake::Fake;
 use once_cell::sync::Lazy;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::path::Path;
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zero2prod::configuration::{SubscriptionSettings, WorkerSettings};
 use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::email_outbox::{try_send_outbox_emails, OutboxRenderer};
+use zero2prod::email_templates::EmailTemplates;
+use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome, IssueCache};
+use zero2prod::unsubscribe::UnsubscribeLinks;
 use zero2prod::{
-    configuration::get_configuration, startup::get_connection_pool, startup::Application, telemetry,
+    configuration::get_configuration, shutdown, startup::get_connection_pool, startup::Application,
+    telemetry,
 };
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -32,6 +42,10 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub worker: WorkerSettings,
+    pub unsubscribe_links: UnsubscribeLinks,
+    pub outbox_renderer: OutboxRenderer,
+    pub subscriptions: SubscriptionSettings,
 }
 
 pub struct ConfirmationLinks {
@@ -41,20 +55,238 @@ pub struct ConfirmationLinks {
 
 impl TestApp {
     pub async fn dispatch_all_pending_emails(&self) {
+        let mut issue_cache = IssueCache::new(self.worker.issue_cache_size);
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.worker,
+                &self.unsubscribe_links,
+                &mut issue_cache,
+            )
+            .await
+            .unwrap()
+            {
+                break;
+            }
+        }
+    }
+
+    pub async fn dispatch_outbox_emails(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = try_send_outbox_emails(
+                &self.db_pool,
+                &self.email_client,
+                &self.outbox_renderer,
+                &self.worker,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    pub async fn get_scheduled_newsletters_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/scheduled", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_edit_newsletter<Body>(&self, issue_id: Uuid, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/edit",
+                &self.address, issue_id
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_cancel_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/cancel",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn follow_redirect(&self, response: &reqwest::Response) -> String {
+        let location = response
+            .headers()
+            .get("Location")
+            .unwrap()
+            .to_str()
+            .unwrap();
+        self.api_client
+            .get(format!("{}{}", &self.address, location))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_newsletter_progress_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_sending_newsletters_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters/sending", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_pause_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/pause",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_resume_newsletter(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/newsletters/{}/resume",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_failed_deliveries_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/deliveries/failures", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_requeue_failed_deliveries<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!(
+                "{}/admin/deliveries/failures/requeue",
+                &self.address
+            ))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    pub async fn get_subscriber_details(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/subscribers/{}",
+                &self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_details_html(&self, subscriber_id: Uuid) -> String {
+        self.get_subscriber_details(subscriber_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `confirm`, `unsubscribe` or `delete`.
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/{}/{}",
+                &self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// `consent_source` is only sent when given.
+    pub async fn post_subscriber_import(
+        &self,
+        csv: &str,
+        mode: &str,
+        consent_source: Option<&str>,
+    ) -> reqwest::Response {
+        let file = reqwest::multipart::Part::text(csv.to_owned())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap();
+        let mut form = reqwest::multipart::Form::new()
+            .part("file", file)
+            .text("mode", mode.to_owned());
+        if let Some(consent_source) = consent_source {
+            form = form.text("consent_source", consent_source.to_owned());
+        }
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -62,6 +294,20 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    /// Submits the form behind a confirmation link.
+    pub async fn post_confirmation(&self, confirmation_link: &reqwest::Url) -> reqwest::Response {
+        let (_, subscription_token) = confirmation_link
+            .query_pairs()
+            .find(|(name, _)| name == "subscription_token")
+            .unwrap();
+        self.api_client
+            .post(format!("{}/subscriptions/confirm", &self.address))
+            .form(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
         // Extract the link from one of the request fields.
@@ -78,8 +324,8 @@ impl TestApp {
             confirmation_link.set_port(Some(self.port)).unwrap();
             confirmation_link
         };
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 
@@ -88,7 +334,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/login", &self.address))
+            .post(format!("{}/login", &self.address))
             .form(body)
             .send()
             .await
@@ -97,7 +343,7 @@ impl TestApp {
 
     pub async fn get_login_html(&self) -> String {
         self.api_client
-            .get(&format!("{}/login", &self.address))
+            .get(format!("{}/login", &self.address))
             .send()
             .await
             .expect("Failed to exectute request")
@@ -108,7 +354,7 @@ impl TestApp {
 
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/dashboard", &self.address))
+            .get(format!("{}/admin/dashboard", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -120,7 +366,7 @@ impl TestApp {
 
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/password", &self.address))
+            .get(format!("{}/admin/password", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -135,7 +381,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/password", &self.address))
+            .post(format!("{}/admin/password", &self.address))
             .form(body)
             .send()
             .await
@@ -144,7 +390,7 @@ impl TestApp {
 
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .post(format!("{}/admin/logout", &self.address))
             .send()
             .await
             .expect("Failed to execute request.")
@@ -152,7 +398,7 @@ impl TestApp {
 
     pub async fn get_publish_newsletter(&self) -> reqwest::Response {
         self.api_client
-            .get(&format!("{}/admin/newsletters", &self.address))
+            .get(format!("{}/admin/newsletters", &self.address))
             .send()
             .await
             .expect("Failed to execute request")
@@ -167,7 +413,7 @@ impl TestApp {
         Body: serde::Serialize,
     {
         self.api_client
-            .post(&format!("{}/admin/newsletters", &self.address))
+            .post(format!("{}/admin/newsletters", &self.address))
             .form(body)
             .send()
             .await
@@ -192,7 +438,9 @@ pub async fn spawn_app() -> TestApp {
 
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application.port());
-    let _ = tokio::spawn(application.run_until_stopped());
+    // Dropping the trigger means the server is never asked to shut down.
+    let (_, shutdown) = shutdown::channel();
+    tokio::spawn(application.run_until_stopped(shutdown));
 
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
@@ -208,6 +456,18 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        worker: configuration.worker,
+        outbox_renderer: OutboxRenderer::new(
+            EmailTemplates::load(Path::new(&configuration.email_templates.directory))
+                .expect("Failed to load email templates."),
+            configuration.application.base_url.clone(),
+            configuration.subscriptions.default_locale,
+        ),
+        unsubscribe_links: UnsubscribeLinks::new(
+            configuration.application.base_url,
+            configuration.application.hmac_secret,
+        ),
+        subscriptions: configuration.subscriptions,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -219,6 +479,26 @@ pub async fn clean_db() {
         .await
         .expect("Failed to connect to Postgres");
 
+    connection
+        .execute("DELETE FROM issue_deliveries")
+        .await
+        .expect("Failed to clean up database, table: issue_deliveries");
+
+    connection
+        .execute("DELETE FROM email_outbox")
+        .await
+        .expect("Failed to clean up database, table: email_outbox");
+
+    connection
+        .execute("DELETE FROM email_outbox_failures")
+        .await
+        .expect("Failed to clean up database, table: email_outbox_failures");
+
+    connection
+        .execute("DELETE FROM email_templates")
+        .await
+        .expect("Failed to clean up database, table: email_templates");
+
     connection
         .execute("DELETE FROM subscription_tokens")
         .await
@@ -234,6 +514,11 @@ pub async fn clean_db() {
         .await
         .expect("Failed to cleanup database, table: issue_delivery_queue.");
 
+    connection
+        .execute("DELETE FROM issue_delivery_failures;")
+        .await
+        .expect("Failed to cleanup database, table: issue_delivery_failures.");
+
     connection
         .execute("DELETE FROM newsletter_issues;")
         .await
@@ -300,9 +585,71 @@ VALUES ($1, $2, $3)",
     }
 }
 
+/// A successful response of Postmark's batch endpoint for `n` messages.
+pub fn batch_accepted(n: usize) -> ResponseTemplate {
+    let results: Vec<_> = (0..n)
+        .map(|_| serde_json::json!({ "ErrorCode": 0, "Message": "OK" }))
+        .collect();
+    ResponseTemplate::new(200).set_body_json(results)
+}
+
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    let name: String = Name().fake();
+    let email: String = SafeEmail().fake();
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": name,
+        "email": email
+    }))
+    .unwrap();
+
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    let email_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(email_request)
+}
+
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
+    app.post_confirmation(&confirmation_link)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
 // Little helper function - we will be doing this check several times throughout
 // this chapter and the next one.
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
+
+/// Publishing an issue right away redirects to its delivery progress page.
+pub fn assert_is_redirect_to_progress_page(response: &reqwest::Response) {
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let issue_id = location.strip_prefix("/admin/newsletters/").unwrap();
+    assert!(Uuid::parse_str(issue_id).is_ok());
+}
@@ -0,0 +1,201 @@
+use crate::helpers::{clean_db, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn post_subscriptions(app: &TestApp, body: &str, accept_language: &str) {
+    app.api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .error_for_status()
+        .unwrap();
+}
+
+/// Subscribes, and returns the confirmation email that was sent.
+async fn subscribe(app: &TestApp, body: &str, accept_language: &str) -> wiremock::Request {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    post_subscriptions(app, body, accept_language).await;
+    app.dispatch_outbox_emails().await;
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+fn subject(email_request: &wiremock::Request) -> String {
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    email["Subject"].as_str().unwrap().to_owned()
+}
+
+async fn saved_locale(app: &TestApp) -> String {
+    sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .locale
+}
+
+#[tokio::test]
+async fn subscribers_get_the_language_of_their_browser() {
+    clean_db().await;
+    let app = spawn_app().await;
+
+    let email_request = subscribe(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "fr-FR, de-DE;q=0.9, en;q=0.8",
+    )
+    .await;
+
+    assert_eq!(subject(&email_request), "Willkommen!");
+    assert_eq!(saved_locale(&app).await, "de");
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    let html_page = reqwest::get(confirmation_links.html.clone())
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(r#"<html lang="de">"#));
+    assert!(html_page.contains("Bitte bestätige dein Abonnement unseres Newsletters."));
+    let html_page = app
+        .post_confirmation(&confirmation_links.html)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Danke, dein Abonnement ist bestätigt!"));
+}
+
+#[tokio::test]
+async fn the_locale_field_of_the_form_takes_precedence_over_the_browser() {
+    clean_db().await;
+    let app = spawn_app().await;
+
+    let email_request = subscribe(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=en",
+        "de",
+    )
+    .await;
+
+    assert_eq!(subject(&email_request), "Welcome!");
+    assert_eq!(saved_locale(&app).await, "en");
+}
+
+#[tokio::test]
+async fn unsupported_languages_fall_back_to_the_default_locale() {
+    clean_db().await;
+    let app = spawn_app().await;
+
+    let email_request = subscribe(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=fr",
+        "fr",
+    )
+    .await;
+
+    assert_eq!(subject(&email_request), "Welcome!");
+    assert_eq!(saved_locale(&app).await, "en");
+}
+
+#[tokio::test]
+async fn template_overrides_are_looked_up_per_locale() {
+    clean_db().await;
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+INSERT INTO email_templates (name, locale, subject, text_body, html_body)
+VALUES ('confirmation', 'de', 'Hallo {{name}}', $1, $1)
+"#,
+        "{{confirmation_link}}",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    let german_email = subscribe(&app, body, "de").await;
+    clean_db().await;
+    let english_email = subscribe(&app, body, "en").await;
+
+    assert_eq!(subject(&german_email), "Hallo le guin");
+    assert_eq!(subject(&english_email), "Welcome!");
+}
+
+#[tokio::test]
+async fn unsubscribe_pages_use_the_locale_of_the_subscriber() {
+    clean_db().await;
+    let app = spawn_app().await;
+    subscribe(
+        &app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "de",
+    )
+    .await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    let unsubscribe_link = format!(
+        "{}/subscriptions/unsubscribe?subscriber_id={}&token={}",
+        app.address,
+        subscriber_id,
+        app.unsubscribe_links.token(subscriber_id)
+    );
+
+    let form_page = reqwest::get(&unsubscribe_link)
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let unsubscribed_page = app
+        .api_client
+        .post(&unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    assert!(form_page.contains("Möchtest du unseren Newsletter nicht mehr erhalten?"));
+    assert!(unsubscribed_page.contains("Du wurdest abgemeldet."));
+}
+
+#[tokio::test]
+async fn error_pages_use_the_language_of_the_browser() {
+    clean_db().await;
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            app.address
+        ))
+        .header("Accept-Language", "de")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Zu diesem Link gibt es kein Abonnement."));
+}
@@ -1,8 +1,15 @@
 mod admin_dashboard;
+mod admin_subscriber_import;
+mod admin_subscribers;
 mod change_password;
 mod health_check;
 mod helpers;
+mod localization;
 mod login;
+mod newsletter_delivery_control;
+mod newsletter_scheduling;
 mod newsletters;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
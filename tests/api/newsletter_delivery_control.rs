@@ -0,0 +1,268 @@
+use crate::helpers::{
+    assert_is_redirect_to, assert_is_redirect_to_progress_page, batch_accepted, clean_db,
+    create_confirmed_subscriber, spawn_app, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to_progress_page(&response);
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn n_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+async fn issue_status(app: &TestApp, issue_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+#[tokio::test]
+async fn paused_issues_are_delivered_only_after_they_are_resumed() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+
+    // Act - Part 1 - Pause the issue
+    let response = app.post_pause_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/sending");
+    let html_page = app.get_sending_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue delivery has been paused.</i></p>"));
+    assert!(html_page.contains("<td>paused</td>"));
+    assert!(html_page.contains(&format!("/admin/newsletters/{}/resume", issue_id)));
+
+    // Act - Part 2 - Nothing goes out while paused
+    {
+        let _mock_guard = Mock::given(path("/email/batch"))
+            .respond_with(batch_accepted(1))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+    assert_eq!(n_queued_deliveries(&app).await, 1);
+
+    // Act - Part 3 - Resume the issue
+    let response = app.post_resume_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/sending");
+    let html_page = app.get_sending_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue delivery has been resumed.</i></p>"));
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(n_queued_deliveries(&app).await, 0);
+    assert_eq!(issue_status(&app, issue_id).await, "published");
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_removes_its_remaining_deliveries() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+    app.post_pause_newsletter(issue_id).await;
+
+    Mock::given(path("/email/batch"))
+        .respond_with(batch_accepted(1))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_cancel_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/sending");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_sending_newsletters_html().await;
+    assert!(html_page.contains(
+        "<p><i>The remaining deliveries of the newsletter issue have been cancelled.</i></p>"
+    ));
+    assert!(!html_page.contains("Newsletter title"));
+    assert_eq!(n_queued_deliveries(&app).await, 0);
+    assert_eq!(issue_status(&app, issue_id).await, "cancelled");
+}
+
+#[tokio::test]
+async fn cancelling_an_issue_keeps_its_failed_deliveries() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+    sqlx::query!(
+        r#"
+WITH failed AS (DELETE FROM issue_delivery_queue RETURNING newsletter_issue_id, subscriber_id)
+INSERT INTO issue_delivery_failures
+(newsletter_issue_id, subscriber_id, n_retries, last_error, failed_at)
+SELECT newsletter_issue_id, subscriber_id, 4, 'boom', now()
+FROM failed
+"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.post_cancel_newsletter(issue_id).await;
+
+    // Assert
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 deliveries failed permanently."));
+}
+
+#[tokio::test]
+async fn only_issues_being_delivered_can_be_paused_or_resumed() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = publish_newsletter(&app).await;
+
+    // Act - Part 1 - Resume an issue that is not paused
+    app.post_resume_newsletter(issue_id).await;
+    let html_page = app.get_sending_newsletters_html().await;
+    assert!(html_page.contains("<p><i>Only paused newsletter issues can be resumed.</i></p>"));
+
+    // Act - Part 2 - Pause a cancelled issue
+    app.post_cancel_newsletter(issue_id).await;
+    app.post_pause_newsletter(issue_id).await;
+    let html_page = app.get_sending_newsletters_html().await;
+    assert!(html_page
+        .contains("<p><i>Only newsletter issues that are being delivered can be paused.</i></p>"));
+
+    // Assert
+    assert_eq!(issue_status(&app, issue_id).await, "cancelled");
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_pause_an_issue() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_pause_newsletter(Uuid::new_v4()).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_progress_page_reports_pending_deliveries() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let issue_id = publish_newsletter(&app).await;
+    let html_page = app.get_newsletter_progress_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains("<h1>Newsletter title</h1>"));
+    assert!(html_page.contains("<tr><th>Recipients</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Remaining</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>0</td></tr>"));
+    assert!(html_page.contains("<tr><th>Started at</th><td>-</td></tr>"));
+    assert!(html_page.contains("<tr><th>Finished at</th><td>-</td></tr>"));
+}
+
+#[tokio::test]
+async fn the_progress_page_reports_the_outcome_of_finished_deliveries() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 0, "Message": "OK" },
+            {
+                "ErrorCode": 400,
+                "Message": "Sender signature not defined for From address."
+            }
+        ])))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let issue_id = publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get_newsletter_progress_html(issue_id).await;
+
+    // Assert
+    assert!(html_page.contains("<tr><th>Recipients</th><td>2</td></tr>"));
+    assert!(html_page.contains("<tr><th>Remaining</th><td>0</td></tr>"));
+    assert!(html_page.contains("<tr><th>Sent</th><td>1</td></tr>"));
+    assert!(html_page.contains("<tr><th>Failed</th><td>1</td></tr>"));
+    assert!(!html_page.contains("<tr><th>Started at</th><td>-</td></tr>"));
+    assert!(!html_page.contains("<tr><th>Finished at</th><td>-</td></tr>"));
+}
+
+#[tokio::test]
+async fn the_progress_page_of_an_unknown_issue_is_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/newsletters/{}",
+            &app.address,
+            Uuid::new_v4()
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,228 @@
+use crate::helpers::{
+    assert_is_redirect_to, batch_accepted, clean_db, create_confirmed_subscriber, spawn_app,
+    TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::Mock;
+use zero2prod::newsletter_scheduler::try_publish_due_issues;
+
+async fn schedule_newsletter(app: &TestApp, publish_at: &str) -> Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+        "publish_at": publish_at,
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn make_due(app: &TestApp, issue_id: Uuid) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET publish_at = now() WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_they_are_due() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email/batch"))
+        .respond_with(batch_accepted(1))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Schedule the issue
+    schedule_newsletter(&app, "2999-01-01T09:30").await;
+    let html_page = app.get_publish_newsletter_html().await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been scheduled for 2999-01-01 09:30 UTC.</i></p>"
+    ));
+
+    // Act - Part 2 - Nothing goes out yet
+    assert_eq!(try_publish_due_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<td>Newsletter title</td>"));
+    assert!(html_page.contains("<td>2999-01-01 09:30 UTC</td>"));
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_they_are_due() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, "2999-01-01T09:30").await;
+    make_due(&app, issue_id).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    assert_eq!(try_publish_due_issues(&app.db_pool).await.unwrap(), 1);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    assert!(issue.published_at.is_some());
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_delivered() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, "2999-01-01T09:30").await;
+    Mock::given(path("/email/batch"))
+        .respond_with(batch_accepted(1))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Cancel
+    let response = app.post_cancel_newsletter(issue_id).await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue has been cancelled.</i></p>"));
+    assert!(!html_page.contains("Newsletter title"));
+
+    // Act - Part 2 - The original date comes and goes
+    make_due(&app, issue_id).await;
+    assert_eq!(try_publish_due_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_edited() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, "2999-01-01T09:30").await;
+
+    // Act
+    let response = app
+        .post_edit_newsletter(
+            issue_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "publish_at": "2999-02-01T10:00",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+
+    // Assert
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The scheduled newsletter issue has been updated.</i></p>"));
+    assert!(html_page.contains("<td>A better title</td>"));
+    assert!(html_page.contains("<td>2999-02-01 10:00 UTC</td>"));
+}
+
+#[tokio::test]
+async fn published_issues_can_no_longer_be_edited() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let issue_id = schedule_newsletter(&app, "2999-01-01T09:30").await;
+    make_due(&app, issue_id).await;
+    try_publish_due_issues(&app.db_pool).await.unwrap();
+
+    // Act
+    let response = app
+        .post_edit_newsletter(
+            issue_id,
+            &serde_json::json!({
+                "title": "A better title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "publish_at": "2999-02-01T10:00",
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters/scheduled");
+    let html_page = app.get_scheduled_newsletters_html().await;
+    assert!(html_page.contains("<p><i>The newsletter issue is no longer scheduled.</i></p>"));
+    let title = sqlx::query!("SELECT title FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .title;
+    assert_eq!(title, "Newsletter title");
+}
+
+#[tokio::test]
+async fn an_invalid_publishing_date_is_rejected() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_publish_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "publish_at": "next tuesday",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_scheduled_issues() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/newsletters/scheduled", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
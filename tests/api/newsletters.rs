@@ -1,58 +1,193 @@
-use crate::helpers::{assert_is_redirect_to, clean_db, spawn_app, ConfirmationLinks, TestApp};
-use fake::faker::internet::en::SafeEmail;
-use fake::faker::name::en::Name;
-use fake::Fake;
+use crate::helpers::{
+    assert_is_redirect_to, assert_is_redirect_to_progress_page, batch_accepted, clean_db,
+    create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app,
+};
 use std::time::Duration;
 use wiremock::matchers::{any, method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::get_configuration;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::shutdown;
 
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let name: String = Name().fake();
-    let email: String = SafeEmail().fake();
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
 
-    let body = serde_urlencoded::to_string(&serde_json::json!({
-        "name": name,
-        "email": email
-    }))
-    .unwrap();
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
 
-    let _mock_guard = Mock::given(path("/email"))
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to_progress_page(&response);
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.follow_redirect(&response).await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we haven't sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
-        .named("Create unconfirmed subscriber")
+        .respond_with(batch_accepted(1))
         .expect(1)
-        .mount_as_scoped(&app.email_server)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Submit newsletter form
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to_progress_page(&response);
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.follow_redirect(&response).await;
+    assert!(html_page.contains(
+        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
+    ));
+    app.dispatch_all_pending_emails().await;
+    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_in_a_single_batch_request() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(3))
+        .expect(1)
+        .mount(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
-        .await
-        .error_for_status()
-        .unwrap();
 
-    let email_request = &app
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to_progress_page(&response);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let batch_request = app
         .email_server
         .received_requests()
         .await
         .unwrap()
         .pop()
         .unwrap();
-    app.get_confirmation_links(email_request)
+    let body: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+    assert_eq!(body.as_array().unwrap().len(), 3);
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await.html;
-    reqwest::get(confirmation_link)
+#[tokio::test]
+async fn newsletters_are_personalized_for_each_subscriber() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(2))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "News for {{subscriber.name}}",
+        "text_content": "Sent to {{subscriber.email}}, leave at {{unsubscribe_url}}",
+        "html_content": r#"<p>Hi {{ subscriber.name }}! <a href="{{unsubscribe_url}}">Leave</a></p>"#,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to_progress_page(&response);
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let batch_request = app
+        .email_server
+        .received_requests()
         .await
         .unwrap()
-        .error_for_status()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+    let subscribers = sqlx::query!("SELECT id, email, name FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
         .unwrap();
+    assert_eq!(subscribers.len(), 2);
+    for subscriber in subscribers {
+        let email = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["To"] == subscriber.email.as_str())
+            .expect("Every subscriber should get their own email");
+        let unsubscribe_url = app.unsubscribe_links.url(subscriber.id);
+        assert_eq!(email["Subject"], format!("News for {}", subscriber.name));
+        assert_eq!(
+            email["TextBody"],
+            format!("Sent to {}, leave at {}", subscriber.email, unsubscribe_url)
+        );
+        assert_eq!(
+            email["HtmlBody"],
+            format!(
+                r#"<p>Hi {}! <a href="{}">Leave</a></p>"#,
+                htmlescape::encode_minimal(&subscriber.name),
+                htmlescape::encode_minimal(&unsubscribe_url)
+            )
+        );
+    }
 }
 
 #[tokio::test]
-async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+async fn newsletters_with_unknown_placeholders_are_rejected() {
     // Arrange
     clean_db().await;
     let app = spawn_app().await;
-    create_unconfirmed_subscriber(&app).await;
+    create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
     Mock::given(any())
@@ -61,57 +196,560 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
         .mount(&app.email_server)
         .await;
 
-    // Act - Part 1 - Submit newsletter form
+    // Act
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
-        "text_content": "Newsletter body as plain text",
+        "text_content": "Hi {{subscriber.first_name}}",
         "html_content": "<p>Newsletter body as HTML</p>",
-        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
     });
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_is_redirect_to(&response, "/admin/newsletters");
 
-    // Act - Part 2 - Follow the redirect
-    let html_page = app.get_publish_newsletter_html().await;
-    assert!(html_page.contains(
-        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
-    ));
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("`subscriber.first_name` is not a known variable"));
+    let issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issues.n, 0);
     app.dispatch_all_pending_emails().await;
-    // Mock verifies on Drop that we haven't sent the newsletter email
 }
 
 #[tokio::test]
-async fn newsletters_are_delivered_to_confirmed_subscribers() {
+async fn successful_deliveries_are_recorded_per_subscriber() {
     // Arrange
     clean_db().await;
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "ErrorCode": 0,
+                "Message": "OK",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d"
+            }])),
+        )
         .expect(1)
         .mount(&app.email_server)
         .await;
 
-    // Act - Part 1 - Submit newsletter form
+    // Act
     let newsletter_request_body = serde_json::json!({
         "title": "Newsletter title",
         "text_content": "Newsletter body as plain text",
         "html_content": "<p>Newsletter body as HTML</p>",
         "idempotency_key": uuid::Uuid::new_v4().to_string()
     });
-    let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delivery = sqlx::query!(
+        r#"
+SELECT d.status, d.provider_message_id, d.n_attempts
+FROM issue_deliveries d
+JOIN subscriptions s ON s.id = d.subscriber_id
+WHERE s.status = 'confirmed'
+"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The delivery should have been recorded");
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+    );
+    assert_eq!(delivery.n_attempts, 1);
+}
+
+#[tokio::test]
+async fn deliveries_that_were_already_sent_are_skipped() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .respond_with(batch_accepted(1))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    // A previous worker delivered the issue, but crashed before dequeuing the task.
+    sqlx::query!(
+        r#"
+INSERT INTO issue_deliveries
+(newsletter_issue_id, subscriber_id, status, n_attempts, created_at, updated_at)
+SELECT newsletter_issue_id, subscriber_id, 'sent', 1, now(), now()
+FROM issue_delivery_queue
+"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    // Mock verifies on Drop that we haven't sent the newsletter email again
+}
+
+#[tokio::test]
+async fn deliveries_interrupted_while_being_sent_are_left_to_an_admin() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    // A previous worker crashed while handing the issue over to the provider.
+    let delivery = sqlx::query!(
+        r#"
+INSERT INTO issue_deliveries
+(newsletter_issue_id, subscriber_id, status, n_attempts, created_at, updated_at)
+SELECT newsletter_issue_id, subscriber_id, 'sending', 0, now(), now()
+FROM issue_delivery_queue
+RETURNING newsletter_issue_id, subscriber_id
+"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - The delivery is not sent again
+    {
+        let _mock_guard = Mock::given(path("/email/batch"))
+            .respond_with(batch_accepted(1))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.dispatch_all_pending_emails().await;
+    }
+    let status = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "unknown");
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 deliveries failed permanently."));
+    assert!(html_page.contains("it may have been delivered already."));
+
+    // Act - Part 2 - An admin decides to send it anyway
+    app.post_requeue_failed_deliveries(&serde_json::json!({}))
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let batch_request = &app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&batch_request.body).unwrap();
+    let header = &body[0]["Headers"][0];
+    assert_eq!(header["Name"], "Message-ID");
+    assert_eq!(
+        header["Value"],
+        format!(
+            "<{}.{}@gmail.com>",
+            delivery.newsletter_issue_id, delivery.subscriber_id
+        )
+    );
+    let status = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "sent");
+}
+
+#[tokio::test]
+async fn deliveries_are_split_according_to_the_configured_batch_size() {
+    // Arrange
+    clean_db().await;
+    let mut app = spawn_app().await;
+    app.worker.batch_size = 2;
+    for _ in 0..3 {
+        create_confirmed_subscriber(&app).await;
+    }
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(2))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+    // Mocks verify on Drop that the deliveries went out in two batches
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_as_soon_as_an_issue_is_published() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let mut configuration = get_configuration().unwrap();
+    configuration.email_client.base_url = app.email_server.uri();
+    // Far longer than the test is willing to wait
+    configuration.worker.poll_interval_milliseconds = 60_000;
+    let (_trigger, shutdown) = shutdown::channel();
+    tokio::spawn(run_worker_until_stopped(configuration, shutdown));
+    // Give the worker time to find the queue empty and start listening
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+
+    // Assert
+    for _ in 0..50 {
+        let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        if queued.count == 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("The worker did not pick up the newsletter issue.");
+}
+
+#[tokio::test]
+async fn transient_delivery_failures_are_rescheduled() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let mock_guard = Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"in_the_future!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The delivery should still be queued");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.in_the_future);
+    // We know it did not go out, it is not in doubt when it is retried.
+    let status = sqlx::query!("SELECT status FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "retrying");
+
+    // The provider is back by the time the delivery is retried
+    drop(mock_guard);
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = sqlx::query!("SELECT status, n_attempts FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "sent");
+    assert_eq!(delivery.n_attempts, 2);
+}
+
+#[tokio::test]
+async fn rescheduled_deliveries_wait_as_long_as_the_provider_asks() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "1800"))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let delay = sqlx::query!(
+        r#"SELECT EXTRACT(EPOCH FROM execute_after - now())::float8 AS "seconds!" FROM issue_delivery_queue"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The delivery should still be queued")
+    .seconds;
+    assert!(
+        (1700.0..=1800.0).contains(&delay),
+        "Rescheduled in {}s",
+        delay
+    );
+}
+
+#[tokio::test]
+async fn deliveries_are_dead_lettered_once_retries_are_exhausted() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    // Pretend we already burnt through all but the last attempt.
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1, execute_after = now()",
+        (app.worker.max_attempts - 1) as i16
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.count, 0);
+    let failure = sqlx::query!("SELECT last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should have been dead-lettered");
+    assert!(failure.last_error.contains("temporarily unavailable"));
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 deliveries failed permanently."));
+}
+
+#[tokio::test]
+async fn rejected_deliveries_are_dead_lettered_immediately() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "ErrorCode": 400,
+                "Message": "Sender signature not defined for From address."
+            }])),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let failure = sqlx::query!("SELECT n_retries, last_error FROM issue_delivery_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("The delivery should have been dead-lettered");
+    assert_eq!(failure.n_retries, 0);
+    assert!(failure.last_error.contains("Sender signature not defined"));
+    let delivery = sqlx::query!("SELECT status, last_error FROM issue_deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(delivery.status, "failed");
+    assert_eq!(delivery.last_error, Some(failure.last_error));
+}
+
+#[tokio::test]
+async fn dead_lettered_deliveries_can_be_requeued() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_publish_newsletter(&newsletter_request_body).await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query!(
+        r#"
+WITH failed AS (DELETE FROM issue_delivery_queue RETURNING newsletter_issue_id, subscriber_id)
+INSERT INTO issue_delivery_failures
+(newsletter_issue_id, subscriber_id, n_retries, last_error, failed_at)
+SELECT newsletter_issue_id, subscriber_id, 4, 'boom', now()
+FROM failed
+"#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    // The subscriber is still the same if their address changed case since
+    sqlx::query!("UPDATE subscriptions SET email = upper(email)")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act - Part 1 - Requeue
+    let response = app
+        .post_requeue_failed_deliveries(&serde_json::json!({
+            "newsletter_issue_id": issue.newsletter_issue_id.to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/deliveries/failures");
 
     // Act - Part 2 - Follow the redirect
-    let html_page = app.get_publish_newsletter_html().await;
-    assert!(html_page.contains(
-        "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
-    ));
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("<p><i>1 deliveries have been requeued.</i></p>"));
+
+    // Act - Part 3 - The worker picks the delivery up again
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_accepted(1))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
     app.dispatch_all_pending_emails().await;
-    // Mock verifies on Drop that we have sent the newsletter email
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_requeue_failed_deliveries() {
+    // Arrange
+    clean_db().await;
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_requeue_failed_deliveries(&serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
 }
 
 #[tokio::test]
@@ -153,9 +791,9 @@ async fn newsletter_creation_is_idempotent() {
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
 
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
-        .respond_with(ResponseTemplate::new(200))
+        .respond_with(batch_accepted(1))
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -171,20 +809,23 @@ async fn newsletter_creation_is_idempotent() {
     });
 
     let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_is_redirect_to(&response, "/admin/newsletters");
+    assert_is_redirect_to_progress_page(&response);
 
     // Act - Part 2 - Follow the redirect
-    let html_page = app.get_publish_newsletter_html().await;
+    let html_page = app.follow_redirect(&response).await;
     assert!(html_page.contains(
         "<p><i>The newsletter issue has been accepted - emails will go out shortly.</i></p>"
     ));
 
     // Act - Part 3 - Submit newsletter form **again**
-    let response = app.post_publish_newsletter(&newsletter_request_body).await;
-    assert_is_redirect_to(&response, "/admin/newsletters");
+    let second_response = app.post_publish_newsletter(&newsletter_request_body).await;
+    assert_eq!(
+        second_response.headers().get("Location"),
+        response.headers().get("Location")
+    );
 
     // Act - Part 4 - Follow the redirect
-    let html_page = app.get_publish_newsletter_html().await;
+    let html_page = app.follow_redirect(&second_response).await;
 
     println!("last HTML: {}", html_page);
     assert!(html_page.contains(
@@ -201,11 +842,11 @@ async fn concurrent_form_submission_is_handled_gracefully() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
     app.test_user.login(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         // Setting a long delay to ensure that the second request
         // arrives before the first one completes
-        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+        .respond_with(batch_accepted(1).set_delay(Duration::from_secs(2)))
         .expect(1)
         .mount(&app.email_server)
         .await;
@@ -0,0 +1,46 @@
+use crate::helpers::spawn_app;
+use std::time::Duration;
+use zero2prod::configuration::get_configuration;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::shutdown;
+use zero2prod::startup::Application;
+
+#[tokio::test]
+async fn the_api_stops_once_shutdown_is_triggered() {
+    // Arrange
+    let mut configuration = get_configuration().unwrap();
+    configuration.application.port = 0;
+    let application = Application::build(configuration).await.unwrap();
+    let (trigger, shutdown) = shutdown::channel();
+    let server = tokio::spawn(application.run_until_stopped(shutdown));
+
+    // Act
+    trigger.trigger();
+
+    // Assert
+    let outcome = tokio::time::timeout(Duration::from_secs(5), server)
+        .await
+        .expect("The API did not stop in time");
+    assert!(outcome.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn idle_workers_stop_once_shutdown_is_triggered() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut configuration = get_configuration().unwrap();
+    configuration.email_client.base_url = app.email_server.uri();
+    configuration.worker.concurrency = 2;
+    let (trigger, shutdown) = shutdown::channel();
+    let worker = tokio::spawn(run_worker_until_stopped(configuration, shutdown));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // Act
+    trigger.trigger();
+
+    // Assert
+    let outcome = tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("The workers did not stop in time");
+    assert!(outcome.unwrap().is_ok());
+}
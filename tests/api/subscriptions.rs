@@ -1,6 +1,7 @@
 use crate::helpers::{clean_db, spawn_app};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::routes::hash_subscription_token;
 
 #[tokio::test]
 async fn subscribe_returns_200_for_valid_form_data() {
@@ -17,6 +18,7 @@ async fn subscribe_returns_200_for_valid_form_data() {
         .await;
 
     let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     assert_eq!(200, response.status().as_u16());
 }
@@ -36,6 +38,7 @@ async fn subscribe_persists_the_new_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     let saved = sqlx::query!("SELECT email, name, status FROM subscriptions",)
         .fetch_one(&app.db_pool)
@@ -105,6 +108,7 @@ async fn subscribe_sends_a_confirmation_email_for_valid_data() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     // Mock asserts on drop
 }
@@ -122,20 +126,222 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text);
 }
 
+#[tokio::test]
+async fn confirmation_emails_use_the_template_override_stored_in_the_database() {
+    let app = spawn_app().await;
+    clean_db().await;
+    sqlx::query!(
+        r#"
+INSERT INTO email_templates (name, subject, text_body, html_body)
+VALUES ('confirmation', 'Hello {{name}}', $1, $2)
+"#,
+        "Confirm here: {{confirmation_link}}",
+        r#"<p>{{name}}, <a href="{{confirmation_link}}">confirm</a></p>"#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin%20%26%20co&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_outbox_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "Hello le guin & co");
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Confirm here: http://"));
+    assert!(email["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>le guin &amp; co, <a href="));
+    let confirmation_links = app.get_confirmation_links(email_request);
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[tokio::test]
+async fn queued_emails_are_rendered_when_they_are_sent() {
+    clean_db().await;
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 0);
+    sqlx::query!(
+        r#"
+INSERT INTO email_templates (name, subject, text_body, html_body)
+VALUES ('confirmation', 'Hello {{name}}', $1, $1)
+"#,
+        "{{confirmation_link}}",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "Hello le guin");
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    app.post_confirmation(&confirmation_link)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribe_marks_the_subscriber_inactive_if_the_provider_refuses_the_address() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 406,
+            "Message": "You tried to send to a recipient that has been marked as inactive."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    // The response does not wait for the email provider
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "inactive");
+}
+
+#[tokio::test]
+async fn subscribe_succeeds_while_the_email_provider_is_down() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .mount_as_scoped(&app.email_server)
+            .await;
+
+        let response = app.post_subscriptions(body.into()).await;
+        app.dispatch_outbox_emails().await;
+
+        assert_eq!(response.status().as_u16(), 200);
+        let outbox = sqlx::query!(
+            "SELECT n_retries, execute_after > now() AS \"postponed!\" FROM email_outbox"
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(outbox.n_retries, 1);
+        assert!(outbox.postponed);
+        // No link went out, so there is no token to store yet
+        let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscription_tokens"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .n;
+        assert_eq!(n_tokens, 0);
+    }
+
+    // The provider is back by the time the email is retried
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    sqlx::query!("UPDATE email_outbox SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_outbox_emails().await;
+
+    let n_outbox = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_outbox, 0);
+}
+
+#[tokio::test]
+async fn confirmation_emails_the_provider_rejects_are_kept_for_admins() {
+    clean_db().await;
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 1000,
+            "Message": "Something is wrong with the message."
+        })))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_outbox_emails().await;
+
+    let n_outbox = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM email_outbox"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_outbox, 0);
+    let failure = sqlx::query!("SELECT kind, last_error FROM email_outbox_failures")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.kind, "confirmation");
+    assert!(failure
+        .last_error
+        .contains("Something is wrong with the message."));
+    app.test_user.login(&app).await;
+    let html_page = app.get_failed_deliveries_html().await;
+    assert!(html_page.contains("1 emails failed permanently."));
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
+
 // #[tokio::test]
 // async fn subscribe_fails_if_there_is_a_fatal_database_error() {
 //     clean_db().await;
 //     let app = spawn_app().await;
 //     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
-//     sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token;",)
+//     sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token_hash;",)
 //         .execute(&app.db_pool)
 //         .await
 //         .unwrap();
@@ -144,3 +350,185 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
 //     assert_eq!(response.status().as_u16(), 500);
 // }
+
+#[tokio::test]
+async fn subscribing_again_while_pending_sends_a_fresh_confirmation_link() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first_response = app.post_subscriptions(body.into()).await;
+    let second_response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 200);
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let first_link = app.get_confirmation_links(&email_requests[0]).html;
+    let second_link = app.get_confirmation_links(&email_requests[1]).html;
+    assert_ne!(first_link, second_link);
+    // Only the latest link works
+    let response = reqwest::get(first_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = reqwest::get(second_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn addresses_that_only_differ_in_case_are_the_same_subscriber() {
+    clean_db().await;
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let response = app
+        .post_subscriptions("name=le%20guin&email=Ursula_Le_Guin%40Gmail.com".into())
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_only_sends_a_notice() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let confirmation_link = {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        app.post_subscriptions(body.into()).await;
+        app.dispatch_outbox_emails().await;
+        let email_request = &app.email_server.received_requests().await.unwrap()[0];
+        app.get_confirmation_links(email_request).html
+    };
+    app.post_confirmation(&confirmation_link)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["Subject"], "You're already subscribed");
+    assert!(!email["TextBody"].as_str().unwrap().contains("http"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_restarts_double_opt_in() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query!(
+        r#"
+INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+VALUES ($1, 'ursula_le_guin@gmail.com', 'ursula', now(), 'unsubscribed')
+"#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+    assert_eq!(saved.name, "le guin");
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    app.post_confirmation(&confirmation_link)
+        .await
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_stores_a_hash_of_the_confirmation_token() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    let (_, token) = confirmation_link
+        .query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .unwrap();
+    let saved = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(saved.subscription_token_hash, token);
+    assert_eq!(
+        saved.subscription_token_hash,
+        hash_subscription_token(&token)
+    );
+}
@@ -1,6 +1,10 @@
-use crate::helpers::{clean_db, spawn_app};
+use crate::helpers::{
+    clean_db, create_confirmed_subscriber, create_unconfirmed_subscriber, spawn_app, TestApp,
+};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::SubscriptionSettings;
+use zero2prod::subscription_cleanup::try_cleanup_subscriptions;
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -29,9 +33,10 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
     // Act
     let response = reqwest::get(confirmation_links.html).await.unwrap();
@@ -41,7 +46,7 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
 }
 
 #[tokio::test]
-async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+async fn submitting_the_confirmation_form_confirms_a_subscriber() {
     clean_db().await;
     let app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
@@ -53,13 +58,13 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
         .await;
 
     app.post_subscriptions(body.into()).await;
+    app.dispatch_outbox_emails().await;
 
     let email_request = &app.email_server.received_requests().await.unwrap()[0];
-    let confirmation_links = app.get_confirmation_links(&email_request);
+    let confirmation_links = app.get_confirmation_links(email_request);
 
-    reqwest::get(confirmation_links.html)
+    app.post_confirmation(&confirmation_links.html)
         .await
-        .unwrap()
         .error_for_status()
         .unwrap();
 
@@ -72,3 +77,248 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    let first_response = app.post_confirmation(&confirmation_links.html).await;
+    let second_response = app.post_confirmation(&confirmation_links.html).await;
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 401);
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 0);
+}
+
+#[tokio::test]
+async fn confirmation_links_do_not_bring_back_subscribers_who_left() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_confirmation(&confirmation_links.html).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn following_the_confirmation_link_only_shows_a_confirmation_form() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions/confirm" method="post">"#));
+    assert!(html_page.contains("Confirm my subscription"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn confirmation_outcomes_are_html_pages() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+
+    let response = app.post_confirmation(&confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>Thank you, your subscription is confirmed!</p>"));
+
+    let mut unknown_link = confirmation_links.html;
+    unknown_link.set_query(Some("subscription_token=unknown"));
+    let response = app.post_confirmation(&unknown_link).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<p>There is no subscriber associated with the provided token.</p>"));
+}
+
+async fn age_subscription(app: &TestApp, hours: i32) {
+    sqlx::query!(
+        "UPDATE subscription_tokens SET created_at = now() - make_interval(hours => $1)",
+        hours
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscriptions SET subscribed_at = now() - make_interval(hours => $1)",
+        hours
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let ttl_hours = app.subscriptions.confirmation_token_ttl_hours as i32;
+    age_subscription(&app, ttl_hours + 1).await;
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscribe again to receive a new confirmation email."));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+fn past_retention_hours(app: &TestApp) -> i32 {
+    (app.subscriptions.confirmation_token_ttl_hours
+        + app.subscriptions.expired_token_retention_hours) as i32
+        + 1
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_still_reported_as_expired_after_cleanup() {
+    clean_db().await;
+    let app = spawn_app().await;
+    let confirmation_links = create_unconfirmed_subscriber(&app).await;
+    let ttl_hours = app.subscriptions.confirmation_token_ttl_hours as i32;
+    age_subscription(&app, ttl_hours + 1).await;
+
+    let report = try_cleanup_subscriptions(&app.db_pool, &app.subscriptions)
+        .await
+        .unwrap();
+
+    assert_eq!(report.n_expired_tokens, 0);
+    let response = app.post_confirmation(&confirmation_links.html).await;
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subscribe again to receive a new confirmation email."));
+}
+
+#[tokio::test]
+async fn cleanup_deletes_expired_tokens_only() {
+    clean_db().await;
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    age_subscription(&app, past_retention_hours(&app)).await;
+    let fresh_link = create_unconfirmed_subscriber(&app).await.html;
+
+    let report = try_cleanup_subscriptions(&app.db_pool, &app.subscriptions)
+        .await
+        .unwrap();
+
+    assert_eq!(report.n_expired_tokens, 1);
+    // Purging is disabled by default
+    assert_eq!(report.n_purged_subscribers, 0);
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 2);
+    let response = reqwest::get(fresh_link).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn cleanup_can_purge_subscribers_that_never_confirmed() {
+    clean_db().await;
+    let app = spawn_app().await;
+    create_unconfirmed_subscriber(&app).await;
+    age_subscription(&app, past_retention_hours(&app)).await;
+    create_confirmed_subscriber(&app).await;
+    let settings = SubscriptionSettings {
+        purge_unconfirmed_subscribers: true,
+        ..app.subscriptions.clone()
+    };
+
+    let report = try_cleanup_subscriptions(&app.db_pool, &settings)
+        .await
+        .unwrap();
+
+    assert_eq!(report.n_expired_tokens, 1);
+    assert_eq!(report.n_purged_subscribers, 1);
+    let remaining = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn cleanup_keeps_subscribers_whose_confirmation_email_failed() {
+    clean_db().await;
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+            "ErrorCode": 1000,
+            "Message": "Something is wrong with the message."
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    app.dispatch_outbox_emails().await;
+    age_subscription(&app, past_retention_hours(&app)).await;
+    let settings = SubscriptionSettings {
+        purge_unconfirmed_subscribers: true,
+        ..app.subscriptions.clone()
+    };
+
+    let report = try_cleanup_subscriptions(&app.db_pool, &settings)
+        .await
+        .unwrap();
+
+    // Admins still have to look into the failure
+    assert_eq!(report.n_purged_subscribers, 0);
+    let n_failures = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM email_outbox_failures"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_failures, 1);
+}